@@ -0,0 +1,364 @@
+use std::fmt::Write;
+
+use crate::{display_width, expand_tabs, LineIndex, ParseError, Span, TAB_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TextColour {
+    None,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+}
+
+impl TextColour {
+    fn paint(&self, s: &str, colour: bool) -> String {
+        let code = match self {
+            TextColour::None => return s.to_string(),
+            _ if !colour || s.is_empty() => return s.to_string(),
+            TextColour::Red => 31,
+            TextColour::Green => 32,
+            TextColour::Yellow => 33,
+            TextColour::Blue => 34,
+        };
+
+        format!("\x1b[{code}m{s}\x1b[0m")
+    }
+}
+
+pub struct Modification<'a> {
+    pub string: &'a str,
+    pub colour: TextColour,
+    pub underline: char,
+}
+
+// Tabs are expanded as the modifications are joined, so the underline stays
+// aligned with the text above it.
+pub fn modify_source(modifications: &[Modification], colour: bool) -> (String, String) {
+    let mut col = 0;
+    let (mut text, mut underline) = (String::new(), String::new());
+    for m in modifications {
+        let (mut t, mut u) = (String::new(), String::new());
+        for c in m.string.chars() {
+            let width = if c == '\t' {
+                TAB_WIDTH - col % TAB_WIDTH
+            } else {
+                1
+            };
+            if c == '\t' {
+                t.push_str(&" ".repeat(width));
+            } else {
+                t.push(c);
+            }
+            u.push_str(&m.underline.to_string().repeat(width));
+            col += width;
+        }
+
+        text.push_str(&m.colour.paint(&t, colour));
+        underline.push_str(&m.colour.paint(&u, colour));
+    }
+
+    (text, underline)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub msg: Option<String>,
+    pub primary: bool,
+}
+
+// Replaces the text in `span` with `replacement`; an empty span inserts.
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub msg: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub msg: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(level: Level, msg: S) -> Self {
+        Diagnostic {
+            level,
+            msg: msg.into(),
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    pub fn error<S: Into<String>>(msg: S) -> Self {
+        Diagnostic::new(Level::Error, msg)
+    }
+
+    pub fn warning<S: Into<String>>(msg: S) -> Self {
+        Diagnostic::new(Level::Warning, msg)
+    }
+
+    pub fn with_label(mut self, span: Span, msg: Option<String>, primary: bool) -> Self {
+        self.labels.push(Label { span, msg, primary });
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help<S: Into<String>>(mut self, help: S) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn with_suggestion<S: Into<String>, R: Into<String>>(
+        mut self,
+        span: Span,
+        replacement: R,
+        msg: S,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            span,
+            replacement: replacement.into(),
+            msg: msg.into(),
+        });
+        self
+    }
+
+    pub fn render(&self, source: &str, colour: bool) -> String {
+        let index = LineIndex::new(source);
+        let lines: Vec<&str> = source.split('\n').collect();
+        let mut out = String::new();
+
+        let (title, title_colour) = match self.level {
+            Level::Error => ("Error", TextColour::Red),
+            Level::Warning => ("Warning", TextColour::Yellow),
+        };
+        writeln!(out, "{}: {}", title_colour.paint(title, colour), self.msg).unwrap();
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.start, !label.primary));
+        let mut last_line = None;
+        for label in labels {
+            let (start, end) = index.span(label.span);
+            for line in start.line..=end.line {
+                let text = lines[line - 1];
+                if last_line.is_some_and(|last| line > last + 1) {
+                    writeln!(out, "...").unwrap();
+                }
+                if last_line != Some(line) {
+                    writeln!(out, "{}{}", gutter(line), expand_tabs(text)).unwrap();
+                }
+                last_line = Some(line);
+
+                let from = if line == start.line { start.col } else { 1 };
+                let to = if line == end.line {
+                    end.col
+                } else {
+                    text.chars().count() + 1
+                };
+                let (from, to) = (display_width(text, from - 1), display_width(text, to - 1));
+                let (marker, marker_colour) = if label.primary {
+                    ('^', title_colour)
+                } else {
+                    ('-', TextColour::Blue)
+                };
+
+                let mut underline = marker.to_string().repeat((to - from).max(1));
+                if line == end.line {
+                    if let Some(msg) = &label.msg {
+                        underline = format!("{underline} {msg}");
+                    }
+                }
+                writeln!(
+                    out,
+                    "{}{}{}",
+                    " ".repeat(gutter(line).len()),
+                    " ".repeat(from),
+                    marker_colour.paint(&underline, colour)
+                )
+                .unwrap();
+            }
+        }
+
+        for note in &self.notes {
+            writeln!(out, "{}: {note}", TextColour::Blue.paint("Note", colour)).unwrap();
+        }
+        for help in &self.help {
+            writeln!(out, "{}: {help}", TextColour::Green.paint("Help", colour)).unwrap();
+        }
+
+        for suggestion in &self.suggestions {
+            writeln!(
+                out,
+                "{}: {}",
+                TextColour::Green.paint("Help", colour),
+                suggestion.msg
+            )
+            .unwrap();
+
+            let start = index.location(suggestion.span.start);
+            let line_start = source[..suggestion.span.start]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            let line_end = source[suggestion.span.end..]
+                .find('\n')
+                .map_or(source.len(), |i| suggestion.span.end + i);
+
+            let modifications = [
+                Modification {
+                    string: &source[line_start..suggestion.span.start],
+                    colour: TextColour::None,
+                    underline: ' ',
+                },
+                Modification {
+                    string: suggestion.span.text(source),
+                    colour: TextColour::Red,
+                    underline: '-',
+                },
+                Modification {
+                    string: &suggestion.replacement,
+                    colour: TextColour::Green,
+                    underline: '+',
+                },
+                Modification {
+                    string: &source[suggestion.span.end..line_end],
+                    colour: TextColour::None,
+                    underline: ' ',
+                },
+            ];
+            let (s, u) = modify_source(&modifications, colour);
+            writeln!(out, "{}{s}", gutter(start.line)).unwrap();
+            writeln!(
+                out,
+                "{}{}",
+                " ".repeat(gutter(start.line).len()),
+                u.trim_end()
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn gutter(line: usize) -> String {
+    format!("[line {line}]: ")
+}
+
+impl<S: std::fmt::Display> From<&ParseError<S>> for Diagnostic {
+    fn from(e: &ParseError<S>) -> Self {
+        match e {
+            ParseError::UnknownNonTerminal(non_term) => {
+                Diagnostic::error(format!("Unknown rule: `{non_term}`"))
+            }
+            ParseError::InvalidGrammar(errors) => errors
+                .iter()
+                .fold(Diagnostic::error("Invalid grammar"), |diagnostic, e| {
+                    diagnostic.with_note(e.to_string())
+                }),
+            ParseError::BadMatchError(e) => {
+                let diagnostic = Diagnostic::error(e.msg.clone()).with_label(
+                    Span::new(e.idx, e.idx),
+                    None,
+                    true,
+                );
+                match e.rules.last() {
+                    Some(rule) => diagnostic.with_note(format!("While parsing `{rule}`.")),
+                    None => diagnostic,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+
+    const SOURCE: &str = "let x = 1\nlet = 2\n";
+
+    #[test]
+    fn labels_notes_and_suggestions() {
+        let diagnostic = Diagnostic::error("Expected a name here.")
+            .with_label(Span::new(14, 14), None, true)
+            .with_label(Span::new(0, 3), Some("like this one".into()), false)
+            .with_note("Every `let` binds a name.")
+            .with_suggestion(Span::new(14, 14), "y ", "Add a name.");
+
+        assert_eq!(
+            diagnostic.render(SOURCE, false),
+            "Error: Expected a name here.\n\
+             [line 1]: let x = 1\n          --- like this one\n\
+             [line 2]: let = 2\n              ^\n\
+             Note: Every `let` binds a name.\n\
+             Help: Add a name.\n\
+             [line 2]: let y = 2\n              ++\n"
+        );
+    }
+
+    #[test]
+    fn labels_can_span_lines() {
+        let diagnostic = Diagnostic::warning("Spans lines").with_label(
+            Span::new(4, 13),
+            Some("here".into()),
+            true,
+        );
+
+        assert_eq!(
+            diagnostic.render(SOURCE, false),
+            "Warning: Spans lines\n\
+             [line 1]: let x = 1\n              ^^^^^\n\
+             [line 2]: let = 2\n          ^^^ here\n"
+        );
+    }
+
+    #[test]
+    fn markers_line_up_after_tabs() {
+        let source = "\tx\ty";
+        let diagnostic = Diagnostic::error("Here").with_label(Span::new(3, 4), None, true);
+
+        assert_eq!(
+            diagnostic.render(source, false),
+            "Error: Here\n[line 1]:     x   y\n                  ^\n"
+        );
+    }
+
+    #[test]
+    fn colour_is_optional() {
+        let diagnostic = Diagnostic::error("Oops").with_label(Span::new(0, 3), None, true);
+
+        assert!(diagnostic
+            .render(SOURCE, true)
+            .contains("\x1b[31m^^^\x1b[0m"));
+        assert!(!diagnostic.render(SOURCE, false).contains('\x1b'));
+    }
+
+    #[test]
+    fn parse_errors_become_diagnostics() {
+        let parser = test_parser(r#"s = "a" "b" ;"#);
+        let e = parser.parse("a c").unwrap_err();
+
+        assert_eq!(
+            Diagnostic::from(&e).render("a c", false),
+            "Error: Expected `b` here.\n[line 1]: a c\n            ^\nNote: While parsing `s`.\n"
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{Diagnostic, Location, ParseError, Span};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSegment {
+    Rule(String),
+    Any,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hint {
+    pub path: Vec<PathSegment>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub source_hint: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Hints {
+    pub hints: Vec<Hint>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HintError {
+    pub msg: String,
+    pub location: Location,
+}
+
+impl std::fmt::Display for HintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Hint Error - {}: {}", self.location, self.msg)
+    }
+}
+
+impl std::error::Error for HintError {}
+
+impl Hint {
+    // `*` stands for any number of rules, so the path is matched against the
+    // whole rule stack like a glob.
+    pub fn matches<S: AsRef<str>>(&self, rules: &[S]) -> bool {
+        fn go<S: AsRef<str>>(path: &[PathSegment], rules: &[S]) -> bool {
+            match path.split_first() {
+                None => rules.is_empty(),
+                Some((PathSegment::Any, rest)) => {
+                    (0..=rules.len()).any(|skip| go(rest, &rules[skip..]))
+                }
+                Some((PathSegment::Rule(rule), rest)) => match rules.split_first() {
+                    Some((first, others)) => first.as_ref() == rule && go(rest, others),
+                    None => false,
+                },
+            }
+        }
+
+        go(&self.path, rules)
+    }
+}
+
+impl Hints {
+    pub fn parse(source: &str) -> Result<Hints, HintError> {
+        let mut cursor = Cursor { source, idx: 0 };
+        let mut hints = vec![];
+        cursor.skip_whitespace();
+        while !cursor.at_end() {
+            hints.push(cursor.hint()?);
+            cursor.skip_whitespace();
+        }
+
+        Ok(Hints { hints })
+    }
+
+    pub fn apply<S: AsRef<str>>(
+        &self,
+        mut diagnostic: Diagnostic,
+        error: &ParseError<S>,
+        source: &str,
+    ) -> Diagnostic {
+        let (idx, rules, captures) = match error {
+            ParseError::BadMatchError(e) => (e.idx, &e.rules, &e.captures),
+            _ => return diagnostic,
+        };
+
+        let interpolate = |template: &str| interpolate(template, rules, captures, source);
+        for hint in self.hints.iter().filter(|hint| hint.matches(rules)) {
+            for note in &hint.notes {
+                diagnostic = diagnostic.with_note(interpolate(note));
+            }
+
+            match &hint.source_hint {
+                Some(source_hint) => {
+                    // Insert right after the last thing the failing rule matched.
+                    let at = captures
+                        .last()
+                        .and_then(|spans| spans.last())
+                        .map_or(idx, |span| span.start + span.text(source).trim_end().len());
+                    let msg = if hint.help.is_empty() {
+                        String::from("Consider making this change.")
+                    } else {
+                        let help: Vec<String> =
+                            hint.help.iter().map(|help| interpolate(help)).collect();
+                        help.join(" ")
+                    };
+                    diagnostic = diagnostic.with_suggestion(
+                        Span::new(at, at),
+                        interpolate(source_hint),
+                        msg,
+                    );
+                }
+                None => {
+                    for help in &hint.help {
+                        diagnostic = diagnostic.with_help(interpolate(help));
+                    }
+                }
+            }
+        }
+
+        diagnostic
+    }
+}
+
+// Replaces `{rule::n}` with the n-th thing matched by the innermost `rule` on
+// the stack. `{{` and `}}` stand for literal braces, and placeholders that
+// don't refer to anything are left as they are.
+fn interpolate<S: AsRef<str>>(
+    template: &str,
+    rules: &[S],
+    captures: &[Vec<Span>],
+    source: &str,
+) -> String {
+    let lookup = |placeholder: &str| {
+        let (rule, n) = placeholder.split_once("::")?;
+        let n: usize = n.trim().parse().ok()?;
+        let depth = rules.iter().rposition(|r| r.as_ref() == rule.trim())?;
+        let span = captures.get(depth)?.get(n)?;
+        Some(span.text(source).trim())
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        match (rest.starts_with('{'), rest.find('}')) {
+            (true, Some(end)) => {
+                match lookup(&rest[1..end]) {
+                    Some(text) => out.push_str(text),
+                    None => out.push_str(&rest[..=end]),
+                }
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+struct Cursor<'s> {
+    source: &'s str,
+    idx: usize,
+}
+
+impl<'s> Cursor<'s> {
+    fn rest(&self) -> &'s str {
+        &self.source[self.idx..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.idx == self.source.len()
+    }
+
+    fn error<T, S: Into<String>>(&self, msg: S) -> Result<T, HintError> {
+        Err(HintError {
+            msg: msg.into(),
+            location: Location::new(self.source, self.idx),
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.idx = self.source.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.idx += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), HintError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(format!("Expected `{token}`"))
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'s str, HintError> {
+        self.skip_whitespace();
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return self.error("Expected a rule name");
+        }
+
+        let ident = &self.rest()[..len];
+        self.idx += len;
+        Ok(ident)
+    }
+
+    fn string(&mut self) -> Result<String, HintError> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            match chars.next() {
+                Some((i, '"')) => {
+                    self.idx += i + 1;
+                    return Ok(s);
+                }
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, c)) => s.push(c),
+                    None => break,
+                },
+                Some((_, c)) => s.push(c),
+                None => break,
+            }
+        }
+
+        self.error("Unterminated string")
+    }
+
+    fn hint(&mut self) -> Result<Hint, HintError> {
+        let mut path = vec![];
+        loop {
+            if self.eat("*") {
+                path.push(PathSegment::Any);
+            } else {
+                path.push(PathSegment::Rule(String::from(self.ident()?)));
+            }
+            if !self.eat("::") {
+                break;
+            }
+        }
+
+        let mut hint = Hint {
+            path,
+            notes: vec![],
+            help: vec![],
+            source_hint: None,
+        };
+
+        self.expect("=>")?;
+        self.expect("[")?;
+        while !self.eat("]") {
+            self.skip_whitespace();
+            let start = self.idx;
+            let key = if self.rest().starts_with('"') {
+                self.string()?
+            } else {
+                String::from(self.ident()?)
+            };
+            self.expect(":")?;
+            let value = self.string()?;
+
+            match key.as_str() {
+                "Note" => hint.notes.push(value),
+                "Help" => hint.help.push(value),
+                "source_hint" => hint.source_hint = Some(value),
+                _ => {
+                    self.idx = start;
+                    return self.error(format!(
+                        "Unknown hint `{key}`, expected `Note`, `Help` or `source_hint`"
+                    ));
+                }
+            }
+
+            if !self.eat(",") {
+                self.expect("]")?;
+                break;
+            }
+        }
+        self.expect(";")?;
+
+        Ok(hint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+
+    fn hint(source: &str) -> Hint {
+        let mut hints = Hints::parse(source).expect("Test hints should parse").hints;
+        assert_eq!(hints.len(), 1);
+        hints.pop().unwrap()
+    }
+
+    #[test]
+    fn hints_are_parsed() {
+        let hint =
+            hint(r#"*::list::item => [ "Note": "a \"note\"", source_hint: ",", "Help": "b\nc" ];"#);
+
+        assert_eq!(
+            hint.path,
+            [
+                PathSegment::Any,
+                PathSegment::Rule("list".into()),
+                PathSegment::Rule("item".into())
+            ]
+        );
+        assert_eq!(hint.notes, [r#"a "note""#]);
+        assert_eq!(hint.help, ["b\nc"]);
+        assert_eq!(hint.source_hint.as_deref(), Some(","));
+    }
+
+    #[test]
+    fn bad_hints_say_where() {
+        let e = Hints::parse("a => [\n  \"Nope\": \"x\" ];").err().unwrap();
+
+        assert!(e.msg.starts_with("Unknown hint `Nope`"));
+        assert_eq!((e.location.line, e.location.col), (2, 3));
+        assert!(Hints::parse(r#"a => [ "Note": "x" ]"#).is_err());
+    }
+
+    fn load(hints: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(Hints::parse(hints)?.hints.len())
+    }
+
+    #[test]
+    fn hint_errors_are_errors() {
+        let e = load("a => [\n  \"Nope\": \"x\" ];").unwrap_err();
+
+        assert!(e
+            .to_string()
+            .starts_with("Hint Error - line 2, column 3: Unknown hint `Nope`"));
+        assert_eq!(load(r#"a => [ "Note": "x" ];"#).unwrap(), 1);
+    }
+
+    #[test]
+    fn paths_match_the_rule_stack_like_a_glob() {
+        let hint = hint(r#"*::list::item => [ "Note": "" ];"#);
+
+        assert!(hint.matches(&["list", "item"]));
+        assert!(hint.matches(&["file", "list", "item"]));
+        assert!(!hint.matches(&["list", "item", "value"]));
+        assert!(!hint.matches(&["list"]));
+    }
+
+    #[test]
+    fn templates_refer_to_what_rules_matched() {
+        let rules = ["list", "item"];
+        let captures = [vec![Span::new(0, 1)], vec![Span::new(2, 5)]];
+        let source = "[ abc";
+
+        assert_eq!(
+            interpolate("{item::0} in {list::0}", &rules, &captures, source),
+            "abc in ["
+        );
+        assert_eq!(
+            interpolate("{{item::0}} {item::1} {nope::0}", &rules, &captures, source),
+            "{item::0} {item::1} {nope::0}"
+        );
+    }
+
+    #[test]
+    fn hints_are_applied_to_matching_errors() {
+        let parser = test_parser(
+            r#"list = "(" (param)+ ")" ; param = ident ":" ident ; ident = re"[a-z]+" ;"#,
+        );
+        let hints = Hints::parse(
+            r#"
+            *::param => [ "Note": "Parameters have types.", source_hint: ": t",
+                          "Help": "Give `{param::0}` a type." ];
+            other => [ "Note": "Not this one." ];
+            "#,
+        )
+        .unwrap();
+        let source = "(a: b c)";
+        let e = parser.parse(source).unwrap_err();
+        let diagnostic = hints.apply(Diagnostic::from(&e), &e, source);
+
+        assert_eq!(diagnostic.notes[1..], ["Parameters have types."]);
+        assert_eq!(diagnostic.suggestions.len(), 1);
+        assert_eq!(diagnostic.suggestions[0].span, Span::new(7, 7));
+        assert_eq!(diagnostic.suggestions[0].msg, "Give `c` a type.");
+    }
+}
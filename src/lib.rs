@@ -1,203 +1,495 @@
-#![feature(try_trait_v2)]
-#![feature(box_patterns)]
-
+mod diagnostic;
+mod hints;
 mod parser;
-use std::collections::HashMap;
 
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+pub use diagnostic::*;
+pub use hints::*;
 pub use parser::*;
 
-pub fn grammar_into_parser<'a>(out: ParseOut<'a>) -> Parser<'a> {
-    assert_eq!(out.rule, "grammar");
-
-    if let ParseGrouping::Sequence { mut ts } = out.out {
-        ts.pop();
-        ts.pop();
-
-        let rules = ts.pop().expect("Should have rules");
-        let mut map = HashMap::new();
-        let mut start = None;
-        match rules.out {
-            ParseGrouping::Sequence { ts: rules } => {
-                for rule in rules {
-                    let (id, meta, rule) = rule_into_parse_expr(rule);
-                    map.insert(id, (rule, meta));
-                    if start.is_none() {
-                        start = Some(id);
-                    }
-                }
+impl Parser {
+    pub fn from_grammar(grammar: &str) -> Result<Parser, GrammarErrors> {
+        let out = Parser::grammar_parser()
+            .parse(grammar)
+            .map_err(|e| GrammarErrors::from(GrammarError::from(e)))?;
+        grammar_into_parser(grammar, out)
+    }
+
+    pub fn from_grammar_file<P: AsRef<Path>>(path: P) -> Result<Parser, GrammarErrors> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(grammar) => Parser::from_grammar(&grammar),
+            Err(e) => Err(GrammarErrors::from(GrammarError::Io {
+                path: path.display().to_string(),
+                msg: e.to_string(),
+            })),
+        }
+    }
+}
+
+// The grammar text being lowered, along with the copy of it that the parser
+// keeps. Spans in the grammar's parse tree are offsets into `text`.
+struct Grammar<'a> {
+    text: &'a str,
+    shared: Arc<str>,
+}
+
+impl<'a> Grammar<'a> {
+    fn slice(&self, span: Span) -> Result<SharedStr, GrammarError> {
+        match self.text.get(span.start..span.end) {
+            Some(_) => Ok(SharedStr::new(&self.shared, span)),
+            None => Err(self.error(span, "Parse tree doesn't match the grammar text")),
+        }
+    }
+
+    // The text of a string literal, without its quotes.
+    fn unquote(&self, span: Span) -> Result<SharedStr, GrammarError> {
+        if span.len() < 2 {
+            return Err(self.error(span, "Expected a quoted string"));
+        }
+        self.slice(Span::new(span.start + 1, span.end - 1))
+    }
+
+    // Like `unquote`, but with escapes such as `\"` and `\n` replaced by the
+    // characters they stand for. Regexes keep theirs, as they're escapes in
+    // the pattern too.
+    fn unescape(&self, span: Span) -> Result<SharedStr, GrammarError> {
+        let text = self.unquote(span)?;
+        if !text.contains('\\') {
+            return Ok(text);
+        }
+
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some(c) => unescaped.push(escaped(c)),
+                None => return Err(self.error(span, "Unfinished escape in string")),
             }
-            _ => unreachable!(),
         }
+        Ok(SharedStr::from(unescaped.as_str()))
+    }
 
-        Parser {
-            rules: map,
-            start: start.expect("Should have at least one rule"),
+    fn error<S: Into<String>>(&self, span: Span, msg: S) -> GrammarError {
+        GrammarError::Syntax {
+            msg: msg.into(),
+            location: self
+                .text
+                .get(..span.start)
+                .map(|_| Location::new(self.text, span.start)),
         }
-    } else {
-        unreachable!()
     }
+
+    // The grammar's parse tree didn't have the shape the grammar parser
+    // gives it, so it can't have come from `Parser::grammar_parser`.
+    fn malformed(&self, rule: &str, span: Span) -> GrammarError {
+        self.error(span, format!("Unexpected `{rule}` in grammar"))
+    }
+
+    fn expect(&self, rule: &str, span: Span, expected: &str) -> Result<(), GrammarError> {
+        if rule == expected {
+            Ok(())
+        } else {
+            Err(self.malformed(rule, span))
+        }
+    }
+}
+
+pub fn grammar_into_parser(grammar: &str, out: ParseOut) -> Result<Parser, GrammarErrors> {
+    let g = &Grammar {
+        text: grammar,
+        shared: Arc::from(grammar),
+    };
+    let mut comments = vec![];
+    collect_comments(g, &out.rule, out.span, &out.out, &mut comments)
+        .map_err(GrammarErrors::from)?;
+    let (definitions, display_names, inline) =
+        grammar_into_definitions(g, &out).map_err(GrammarErrors::from)?;
+
+    let mut parser = Parser::from_definitions(g.shared.clone(), definitions)?;
+    parser.display_names = display_names;
+    parser.inline = inline;
+    parser.comments = comments;
+    Ok(parser)
+}
+
+// Comments are whitespace as far as the rules are concerned, so they're
+// gathered up before lowering throws the whitespace away.
+fn collect_comments(
+    g: &Grammar,
+    rule: &str,
+    span: Span,
+    out: &ParseGrouping,
+    comments: &mut Vec<SharedStr>,
+) -> Result<(), GrammarError> {
+    match out {
+        ParseGrouping::Terminal(_) if rule == COMMENT => comments.push(g.slice(span)?),
+        ParseGrouping::Sequence { ts } => {
+            for t in ts.iter() {
+                collect_comments(g, &t.rule, t.span, &t.out, comments)?;
+            }
+        }
+        ParseGrouping::Optional(Some(group)) => collect_comments(g, rule, span, group, comments)?,
+        ParseGrouping::Out(out) => collect_comments(g, &out.rule, out.span, &out.out, comments)?,
+        ParseGrouping::Terminal(_)
+        | ParseGrouping::Optional(None)
+        | ParseGrouping::Error { .. } => {}
+    }
+    Ok(())
+}
+
+// A piece of what a rule matched in the grammar's parse tree: either a call to
+// another rule, or text the rule matched itself.
+#[derive(Clone, Copy)]
+enum Part<'o, 'a> {
+    Call(&'o ParseOut<'a>),
+    Text(&'a str),
 }
 
-fn rule_into_parse_expr<'a>(out: ParseOut<'a>) -> (&'a str, bool, ParseExpr<'a>) {
-    assert_eq!(out.rule, "rule");
-    match out.out {
-        ParseGrouping::Sequence { mut ts } => {
-            ts.pop(); // ws
-            ts.pop(); // ";"
-            ts.pop(); // ws
-            let sequence = ts.pop().expect("Expected a seqeuence");
-            ts.pop(); // ws
-            ts.pop(); // "="
-            ts.pop(); // ws
-            let non_terminal = ts.pop().expect("Expected a non terminal");
-            let meta = ts.pop().expect("Expected some meta...");
+// What `out` matched, in order, however it was grouped. Rules are lowered by
+// looking at the names of the rules they called, so the grammar parser's
+// rules can change shape without lowering having to follow. Whitespace and
+// comments are left out.
+fn parts<'o, 'a>(out: &'o ParseOut<'a>) -> Vec<Part<'o, 'a>> {
+    let mut parts = vec![];
+    collect_parts(&out.out, &mut parts);
+    parts
+}
 
-            let meta = match meta.out {
-                ParseGrouping::Optional(None) => false,
-                ParseGrouping::Optional(Some(box ParseGrouping::Terminal("@"))) => true,
-                _ => todo!(),
-            };
+fn collect_parts<'o, 'a>(group: &'o ParseGrouping<'a>, parts: &mut Vec<Part<'o, 'a>>) {
+    let mut child = |t: &'o ParseOut<'a>| match t.rule.as_str() {
+        _ if !t.call => collect_parts(&t.out, parts),
+        "ws" | "sep" => {}
+        _ => parts.push(Part::Call(t)),
+    };
+    match group {
+        ParseGrouping::Terminal(text) if !text.is_empty() => parts.push(Part::Text(text)),
+        ParseGrouping::Sequence { ts } => ts.iter().for_each(child),
+        ParseGrouping::Optional(Some(group)) => collect_parts(group, parts),
+        ParseGrouping::Out(out) => child(out),
+        ParseGrouping::Terminal(_)
+        | ParseGrouping::Optional(None)
+        | ParseGrouping::Error { .. } => {}
+    }
+}
 
-            let id = match non_terminal.out {
-                ParseGrouping::Terminal(id) => id,
-                s => unreachable!("{s:?}"),
-            };
+type Definitions = Vec<(SharedStr, ParseExpr, bool)>;
+type DisplayNames = HashMap<SharedStr, SharedStr>;
 
-            let parse_expr = seqeuence_into_parse_expr(sequence);
-            (id, meta, parse_expr)
+fn grammar_into_definitions(
+    g: &Grammar,
+    out: &ParseOut,
+) -> Result<(Definitions, DisplayNames, HashSet<SharedStr>), GrammarError> {
+    g.expect(&out.rule, out.span, "grammar")?;
+
+    let mut definitions = vec![];
+    let mut display_names = HashMap::new();
+    let mut inline = HashSet::new();
+    for part in parts(out) {
+        let rule = match part {
+            Part::Call(rule) => rule,
+            Part::Text(_) => return Err(g.malformed(&out.rule, out.span)),
+        };
+        let (id, meta, is_inline, display_name, rule) = rule_into_parse_expr(g, rule)?;
+        if let Some(name) = display_name {
+            display_names.insert(id.clone(), name);
+        }
+        if is_inline {
+            inline.insert(id.clone());
         }
-        _ => unreachable!(),
+        definitions.push((id, rule, meta));
     }
+
+    Ok((definitions, display_names, inline))
 }
 
-fn seqeuence_into_parse_expr<'a>(out: ParseOut<'a>) -> ParseExpr<'a> {
-    assert_eq!(out.rule, "sequence");
-    match out.out {
-        ParseGrouping::Out(out) => match &out.rule {
-            &"sequence" => seqeuence_into_parse_expr(*out),
-            &"modifier" => modifier_into_parse_expr(*out, true),
-            _ => unreachable!(),
-        },
-        ParseGrouping::Sequence { mut ts } => {
-            match ts.len() {
-                3 => {
-                    let sequence = ts.pop().expect("Expected a sequence");
-                    ts.pop(); // ws
-                    let modifier = ts.pop().expect("Expected a modifier");
-
-                    let e1 = modifier_into_parse_expr(modifier, true);
-                    let e2 = seqeuence_into_parse_expr(sequence);
-                    ParseExpr::Sequence { es: vec![e1, e2] }
-                }
-                5 => {
-                    let sequence = ts.pop().unwrap();
-                    ts.pop();
-                    ts.pop();
-                    ts.pop();
-                    let modifier = ts.pop().unwrap();
-
-                    let e1 = modifier_into_parse_expr(modifier, true);
-                    let e2 = seqeuence_into_parse_expr(sequence);
-
-                    ParseExpr::Choice { es: vec![e1, e2] }
-                }
-                _ => unreachable!(),
-            }
+type RuleDefinition = (SharedStr, bool, bool, Option<SharedStr>, ParseExpr);
+
+fn rule_into_parse_expr(g: &Grammar, out: &ParseOut) -> Result<RuleDefinition, GrammarError> {
+    g.expect(&out.rule, out.span, "rule")?;
+
+    let mut meta = false;
+    let mut inline = false;
+    let mut id = None;
+    let mut display_name = None;
+    let mut parse_expr = None;
+    for part in parts(out) {
+        match part {
+            Part::Text("@") => meta = true,
+            Part::Text("-") => inline = true,
+            Part::Text("=" | ";") => {}
+            Part::Call(t) if t.rule == "non_terminal" => id = Some(g.slice(t.span)?),
+            Part::Call(t) if t.rule == "STRING" => display_name = Some(g.unescape(t.span)?),
+            Part::Call(t) if t.rule == "choice" => parse_expr = Some(choice_into_parse_expr(g, t)?),
+            _ => return Err(g.malformed(&out.rule, out.span)),
         }
-        _ => todo!(),
+    }
+
+    match (id, parse_expr) {
+        (Some(id), Some(parse_expr)) => Ok((id, meta, inline, display_name, parse_expr)),
+        _ => Err(g.malformed(&out.rule, out.span)),
     }
 }
 
-fn modifier_into_parse_expr<'a>(out: ParseOut<'a>, allow_whitespace: bool) -> ParseExpr<'a> {
-    assert_eq!(out.rule, "modifier");
-    match out.out {
-        ParseGrouping::Out(out) => match out.rule {
-            "primary" => primary_into_parse_expr(*out, allow_whitespace),
-            "modifier" => modifier_into_parse_expr(*out, allow_whitespace),
-            _ => unreachable!(),
-        },
-        ParseGrouping::Sequence { mut ts } => {
-            let modifier = ts.pop().expect("Expected one of +, *, ?");
-            let primary = ts.pop().expect("Expected primary");
-
-            if let ParseGrouping::Terminal(modifier) = modifier.out {
-                match modifier {
-                    "+" => ParseExpr::OneOrMore {
-                        e: Box::new(primary_into_parse_expr(primary, true)),
-                    },
-                    "*" => ParseExpr::ZeroOrMore {
-                        e: Box::new(primary_into_parse_expr(primary, true)),
-                    },
-                    "?" => ParseExpr::Optional {
-                        e: Box::new(primary_into_parse_expr(primary, true)),
-                    },
-                    _ => unreachable!(),
-                }
-            } else if let ParseGrouping::Terminal("_") = primary.out {
-                primary_into_parse_expr(modifier, false)
-            } else {
-                unreachable!()
-            }
+// `a | b | c` may be parsed as `a | (b | c)`, but is lowered to a single
+// choice.
+fn choice_into_parse_expr(g: &Grammar, out: &ParseOut) -> Result<ParseExpr, GrammarError> {
+    let mut es = vec![];
+    choice_into_parse_exprs(g, out, &mut es)?;
+    if es.len() == 1 {
+        Ok(es.pop().unwrap())
+    } else {
+        Ok(ParseExpr::Choice { es })
+    }
+}
+
+fn choice_into_parse_exprs(
+    g: &Grammar,
+    out: &ParseOut,
+    es: &mut Vec<ParseExpr>,
+) -> Result<(), GrammarError> {
+    g.expect(&out.rule, out.span, "choice")?;
+    for part in parts(out) {
+        match part {
+            Part::Text("|") => {}
+            Part::Call(t) if t.rule == "choice" => choice_into_parse_exprs(g, t, es)?,
+            Part::Call(t) if t.rule == "sequence" => es.push(sequence_into_parse_expr(g, t)?),
+            _ => return Err(g.malformed(&out.rule, out.span)),
+        }
+    }
+    Ok(())
+}
+
+// Like choices, sequences are flattened as they're lowered.
+fn sequence_into_parse_expr(g: &Grammar, out: &ParseOut) -> Result<ParseExpr, GrammarError> {
+    let mut es = vec![];
+    sequence_into_parse_exprs(g, out, &mut es)?;
+    if es.len() == 1 {
+        Ok(es.pop().unwrap())
+    } else {
+        Ok(ParseExpr::Sequence { es })
+    }
+}
+
+fn sequence_into_parse_exprs(
+    g: &Grammar,
+    out: &ParseOut,
+    es: &mut Vec<ParseExpr>,
+) -> Result<(), GrammarError> {
+    g.expect(&out.rule, out.span, "sequence")?;
+    for part in parts(out) {
+        match part {
+            Part::Call(t) if t.rule == "sequence" => sequence_into_parse_exprs(g, t, es)?,
+            Part::Call(t) if t.rule == "modifier" => es.push(modifier_into_parse_expr(g, t, true)?),
+            _ => return Err(g.malformed(&out.rule, out.span)),
         }
-        x => todo!("{x:?}"),
     }
+    Ok(())
 }
 
-fn primary_into_parse_expr<'a>(out: ParseOut<'a>, allow_whitespace: bool) -> ParseExpr<'a> {
-    assert_eq!(out.rule, "primary");
-    match out.out {
-        ParseGrouping::Out(out) => match out.rule {
-            "primary" => primary_into_parse_expr(*out, allow_whitespace),
-            "atomic" => atomic_into_parse_expr(*out, allow_whitespace),
-            _ => unreachable!(),
+fn modifier_into_parse_expr(
+    g: &Grammar,
+    out: &ParseOut,
+    allow_whitespace: bool,
+) -> Result<ParseExpr, GrammarError> {
+    g.expect(&out.rule, out.span, "modifier")?;
+    let e = match parts(out).as_slice() {
+        [Part::Call(t)] if t.rule == "modifier" => {
+            modifier_into_parse_expr(g, t, allow_whitespace)?
+        }
+        [Part::Call(primary)] => primary_into_parse_expr(g, primary, allow_whitespace)?,
+        [Part::Text("_"), Part::Call(primary)] => primary_into_parse_expr(g, primary, false)?,
+        [Part::Text("&"), Part::Call(modifier)] => ParseExpr::And {
+            e: Box::new(modifier_into_parse_expr(g, modifier, allow_whitespace)?),
         },
-        ParseGrouping::Sequence { mut ts } => {
-            ts.pop();
-            ts.pop();
-            let sequence = ts.pop().unwrap();
-            ts.pop();
-            ts.pop();
-            seqeuence_into_parse_expr(sequence)
-        }
-        _ => todo!(),
-    }
-}
-
-fn atomic_into_parse_expr<'a>(out: ParseOut<'a>, allow_whitespace: bool) -> ParseExpr<'a> {
-    assert_eq!(out.rule, "atomic");
-    let e = match out.out {
-        ParseGrouping::Out(out) => match (out.rule, out.out) {
-            ("regex", ParseGrouping::Sequence { ts }) => {
-                if let Some(ParseOut {
-                    out: ParseGrouping::Terminal(term),
-                    ..
-                }) = ts.get(1)
-                {
-                    ParseExpr::Atomic(AtomicExpr::Regex(&term[1..term.len() - 1]))
-                } else {
-                    todo!("err...")
-                }
+        [Part::Text("!"), Part::Call(modifier)] => ParseExpr::Not {
+            e: Box::new(modifier_into_parse_expr(g, modifier, allow_whitespace)?),
+        },
+        [Part::Call(primary), Part::Text(op)] => {
+            let e = Box::new(primary_into_parse_expr(g, primary, true)?);
+            match *op {
+                "+" => ParseExpr::OneOrMore { e },
+                "*" => ParseExpr::ZeroOrMore { e },
+                "?" => ParseExpr::Optional { e },
+                _ => return Err(g.malformed(&out.rule, out.span)),
             }
-            ("non_terminal", ParseGrouping::Terminal(term)) => {
-                if term == "EOF" {
-                    ParseExpr::Atomic(AtomicExpr::EndOfFile)
-                } else {
-                    ParseExpr::Atomic(AtomicExpr::NonTerminal(term))
-                }
+        }
+        [Part::Call(primary), Part::Call(bounds)] => {
+            let (min, max) = bounds_into_counts(g, bounds)?;
+            ParseExpr::Repeat {
+                e: Box::new(primary_into_parse_expr(g, primary, true)?),
+                min,
+                max,
             }
-            ("terminal" | "STRING", ParseGrouping::Terminal(term)) => {
-                ParseExpr::Atomic(AtomicExpr::Terminal(&term[1..term.len() - 1]))
+        }
+        [Part::Call(e), Part::Text(op), Part::Call(other)] => {
+            let e = Box::new(primary_into_parse_expr(g, e, allow_whitespace)?);
+            let other = Box::new(primary_into_parse_expr(g, other, allow_whitespace)?);
+            match *op {
+                "~" => ParseExpr::Recover { e, sync: other },
+                "%" => ParseExpr::Separated {
+                    e,
+                    sep: other,
+                    trailing: false,
+                },
+                "%%" => ParseExpr::Separated {
+                    e,
+                    sep: other,
+                    trailing: true,
+                },
+                _ => return Err(g.malformed(&out.rule, out.span)),
             }
-            (r, o) => unreachable!("{r}, {o:?}"),
+        }
+        _ => return Err(g.malformed(&out.rule, out.span)),
+    };
+    Ok(e)
+}
+
+// The counts in `{n}`, `{m,}` or `{m,n}`, with no maximum for `{m,}`.
+fn bounds_into_counts(g: &Grammar, out: &ParseOut) -> Result<(usize, Option<usize>), GrammarError> {
+    g.expect(&out.rule, out.span, "bounds")?;
+    let parts = parts(out);
+    let mut counts = vec![];
+    for part in &parts {
+        if let Part::Call(count) = part {
+            counts.push(count_into_usize(g, count)?);
+        }
+    }
+    let open = parts.iter().any(|part| matches!(part, Part::Text(",")));
+
+    match (counts.as_slice(), open) {
+        ([n], false) => Ok((*n, Some(*n))),
+        ([min], true) => Ok((*min, None)),
+        ([min, max], true) => Ok((*min, Some(*max))),
+        _ => Err(g.malformed(&out.rule, out.span)),
+    }
+}
+
+fn count_into_usize(g: &Grammar, out: &ParseOut) -> Result<usize, GrammarError> {
+    g.expect(&out.rule, out.span, "count")?;
+    let count = g.slice(out.span)?;
+    count
+        .as_str()
+        .parse()
+        .map_err(|_| g.error(out.span, format!("Repetition count `{count}` is too large")))
+}
+
+fn primary_into_parse_expr(
+    g: &Grammar,
+    out: &ParseOut,
+    allow_whitespace: bool,
+) -> Result<ParseExpr, GrammarError> {
+    g.expect(&out.rule, out.span, "primary")?;
+    match parts(out).as_slice() {
+        [Part::Text("("), Part::Call(choice), Part::Text(")")] => choice_into_parse_expr(g, choice),
+        [Part::Call(t)] if t.rule == "primary" => primary_into_parse_expr(g, t, allow_whitespace),
+        [Part::Call(atomic)] => atomic_into_parse_expr(g, atomic, allow_whitespace),
+        _ => Err(g.malformed(&out.rule, out.span)),
+    }
+}
+
+fn atomic_into_parse_expr(
+    g: &Grammar,
+    out: &ParseOut,
+    allow_whitespace: bool,
+) -> Result<ParseExpr, GrammarError> {
+    g.expect(&out.rule, out.span, "atomic")?;
+    let atom = match parts(out).as_slice() {
+        [Part::Call(atom)] => *atom,
+        _ => return Err(g.malformed(&out.rule, out.span)),
+    };
+
+    let e = match (atom.rule.as_str(), parts(atom).as_slice()) {
+        ("terminal", [Part::Call(string)]) if string.rule == "STRING" => {
+            AtomicExpr::Terminal(g.unescape(string.span)?)
+        }
+        ("regex", [Part::Text("re"), Part::Call(string)]) if string.rule == "STRING" => {
+            AtomicExpr::Regex(g.unquote(string.span)?)
+        }
+        ("class" | "range", _) => match CharClass::parse(g.slice(atom.span)?) {
+            Some(class) => AtomicExpr::Class(class),
+            None => return Err(g.malformed(&atom.rule, atom.span)),
+        },
+        ("non_terminal", _) => match g.slice(atom.span)? {
+            id if id == "EOF" => AtomicExpr::EndOfFile,
+            id => AtomicExpr::NonTerminal(id),
         },
-        _ => unreachable!(),
+        _ => return Err(g.malformed(&atom.rule, atom.span)),
     };
 
     if allow_whitespace {
-        ParseExpr::Sequence {
-            es: vec![ParseExpr::Atomic(AtomicExpr::Regex("\\s*")), e],
-        }
+        Ok(ParseExpr::SkipTrivia {
+            e: Box::new(ParseExpr::Atomic(e)),
+        })
     } else {
-        e
+        Ok(ParseExpr::Atomic(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+    use std::error::Error;
+    use std::sync::OnceLock;
+
+    fn parse_with(grammar: &str, source: &str) -> Result<Span, Box<dyn Error>> {
+        let parser = Parser::from_grammar(grammar)?;
+        Ok(parser.parse(source)?.span)
+    }
+
+    #[test]
+    fn grammar_errors_are_one_error() {
+        assert_eq!(parse_with(r#"s = "a" ;"#, "a").unwrap(), Span::new(0, 1));
+
+        let e = parse_with("s = a b ;", "").unwrap_err();
+        let errors = e.downcast_ref::<GrammarErrors>().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(e.to_string().lines().count(), 2);
+    }
+
+    #[test]
+    fn unreadable_grammar_files_are_grammar_errors() {
+        let errors = Parser::from_grammar_file("no/such/grammar.lex")
+            .err()
+            .unwrap();
+
+        assert!(matches!(&errors[..], [GrammarError::Io { .. }]));
+    }
+
+    // Built from a grammar that's gone by the time the parser is used.
+    fn load() -> Parser {
+        let grammar = String::from(r#"s = (word)+ ; word = re"[a-z]+" ;"#);
+        test_parser(&grammar)
+    }
+
+    #[test]
+    fn parsers_own_their_grammar() {
+        static REGISTRY: OnceLock<Parser> = OnceLock::new();
+        let parser: &'static Parser = REGISTRY.get_or_init(load);
+
+        let out = {
+            let source = String::from("ab cd");
+            parser.parse(&source).unwrap().span
+        };
+        assert_eq!(out, Span::new(0, 5));
+        assert!(parser.rules().contains_key("word"));
+    }
+
+    #[test]
+    fn parsers_can_move_between_threads() {
+        let parser = load();
+        let span = std::thread::spawn(move || parser.parse("ab").unwrap().span)
+            .join()
+            .unwrap();
+
+        assert_eq!(span, Span::new(0, 2));
     }
 }
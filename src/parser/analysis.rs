@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+
+use super::{AtomicExpr, ParseExpr, Parser, SharedStr};
+
+impl Parser {
+    pub fn nullable_rules(&self) -> HashSet<SharedStr> {
+        let mut nullable = HashSet::new();
+        loop {
+            let before = nullable.len();
+            for (id, (rule, _)) in &self.rules {
+                if !nullable.contains(id) && rule.is_nullable(&nullable, self) {
+                    nullable.insert(id.clone());
+                }
+            }
+
+            if nullable.len() == before {
+                return nullable;
+            }
+        }
+    }
+
+    pub fn left_recursive_rules(&self) -> HashSet<SharedStr> {
+        let nullable = self.nullable_rules();
+        let mut left_recursive = HashSet::new();
+        for (id, (rule, _)) in &self.rules {
+            let mut seen = HashSet::new();
+            let mut stack = vec![];
+            rule.left_calls(&nullable, self, &mut stack);
+            while let Some(call) = stack.pop() {
+                if call == *id {
+                    left_recursive.insert(id.clone());
+                    break;
+                }
+                if seen.insert(call.clone()) {
+                    if let Some((rule, _)) = self.rules.get(&call) {
+                        rule.left_calls(&nullable, self, &mut stack);
+                    }
+                }
+            }
+        }
+
+        left_recursive
+    }
+}
+
+impl ParseExpr {
+    pub fn is_nullable(&self, nullable: &HashSet<SharedStr>, parser: &Parser) -> bool {
+        match self {
+            ParseExpr::Atomic(AtomicExpr::Terminal(term)) => term.is_empty(),
+            ParseExpr::Atomic(AtomicExpr::Regex(re_str)) => {
+                parser.regexes.get(re_str).is_some_and(|re| re.is_match(""))
+            }
+            ParseExpr::Atomic(AtomicExpr::Class(_)) => false,
+            ParseExpr::Atomic(AtomicExpr::NonTerminal(non_term)) => nullable.contains(non_term),
+            ParseExpr::Atomic(AtomicExpr::EndOfFile) => true,
+            ParseExpr::Sequence { es } => es.iter().all(|e| e.is_nullable(nullable, parser)),
+            ParseExpr::Choice { es } => es.iter().any(|e| e.is_nullable(nullable, parser)),
+            ParseExpr::OneOrMore { e }
+            | ParseExpr::Recover { e, .. }
+            | ParseExpr::SkipTrivia { e } => e.is_nullable(nullable, parser),
+            ParseExpr::Repeat { e, min, .. } => *min == 0 || e.is_nullable(nullable, parser),
+            ParseExpr::Separated { e, .. } => e.is_nullable(nullable, parser),
+            ParseExpr::ZeroOrMore { .. }
+            | ParseExpr::Optional { .. }
+            | ParseExpr::And { .. }
+            | ParseExpr::Not { .. } => true,
+        }
+    }
+
+    // The rules that can be entered before this expression consumes any input.
+    fn left_calls(&self, nullable: &HashSet<SharedStr>, parser: &Parser, out: &mut Vec<SharedStr>) {
+        match self {
+            ParseExpr::Atomic(AtomicExpr::NonTerminal(non_term)) => out.push(non_term.clone()),
+            ParseExpr::Atomic(_) => (),
+            ParseExpr::Sequence { es } => {
+                for e in es {
+                    e.left_calls(nullable, parser, out);
+                    if !e.is_nullable(nullable, parser) {
+                        break;
+                    }
+                }
+            }
+            ParseExpr::Choice { es } => es.iter().for_each(|e| e.left_calls(nullable, parser, out)),
+            ParseExpr::Separated { e, sep, .. } => {
+                e.left_calls(nullable, parser, out);
+                if e.is_nullable(nullable, parser) {
+                    sep.left_calls(nullable, parser, out);
+                }
+            }
+            ParseExpr::ZeroOrMore { e }
+            | ParseExpr::OneOrMore { e }
+            | ParseExpr::Optional { e }
+            | ParseExpr::Repeat { e, .. }
+            | ParseExpr::SkipTrivia { e }
+            | ParseExpr::And { e }
+            | ParseExpr::Not { e }
+            | ParseExpr::Recover { e, .. } => e.left_calls(nullable, parser, out),
+        }
+    }
+}
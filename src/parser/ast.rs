@@ -0,0 +1,228 @@
+use super::{ParseError, ParseGrouping, ParseOut, Parser, SharedStr, Span};
+
+// An abstract syntax tree, with one node per call to a rule that matched more
+// than one thing worth keeping. Whitespace and punctuation written as string
+// literals in the grammar are left out, and a rule that matched just one
+// thing is replaced by it.
+#[derive(Debug, Clone)]
+pub enum Ast<'a> {
+    Node {
+        rule: SharedStr,
+        span: Span,
+        children: Vec<Ast<'a>>,
+    },
+    Leaf {
+        rule: SharedStr,
+        text: &'a str,
+        span: Span,
+    },
+}
+
+impl<'a> Ast<'a> {
+    pub fn rule(&self) -> &SharedStr {
+        match self {
+            Ast::Node { rule, .. } | Ast::Leaf { rule, .. } => rule,
+        }
+    }
+
+    // Where the tree is in the source, including any punctuation left out of
+    // it at either end.
+    pub fn span(&self) -> Span {
+        match self {
+            Ast::Node { span, .. } | Ast::Leaf { span, .. } => *span,
+        }
+    }
+
+    pub fn children(&self) -> &[Ast<'a>] {
+        match self {
+            Ast::Node { children, .. } => children,
+            Ast::Leaf { .. } => &[],
+        }
+    }
+}
+
+impl Parser {
+    // Parses `source` like `parse` does, into an abstract tree. The root is
+    // always a node for the start rule, however little it matched.
+    pub fn parse_ast<'a>(&self, source: &'a str) -> Result<Ast<'a>, ParseError<String>> {
+        let out = self.parse(source)?;
+
+        let builder = AstBuilder {
+            parser: self,
+            source,
+        };
+        let mut children = vec![];
+        let mut extent = None;
+        builder.walk_group(&out, &out.out, &mut children, &mut extent);
+
+        Ok(Ast::Node {
+            rule: out.rule,
+            span: extent.unwrap_or(Span::new(out.span.end, out.span.end)),
+            children,
+        })
+    }
+}
+
+struct AstBuilder<'p, 'a> {
+    parser: &'p Parser,
+    source: &'a str,
+}
+
+impl<'p, 'a> AstBuilder<'p, 'a> {
+    // Adds what `out` matched to `children`. `extent` grows to cover
+    // everything matched other than whitespace, kept in the tree or not.
+    fn walk(&self, out: &ParseOut<'a>, children: &mut Vec<Ast<'a>>, extent: &mut Option<Span>) {
+        // Only a call to a rule can become a node; anything else is part of
+        // the call it's in.
+        if !out.call {
+            return self.walk_group(out, &out.out, children, extent);
+        }
+
+        let mut inner = vec![];
+        let mut inner_extent = None;
+        self.walk_group(out, &out.out, &mut inner, &mut inner_extent);
+        let span = match inner_extent {
+            Some(span) => span,
+            None => return,
+        };
+        cover(extent, span);
+
+        // A rule that only matched punctuation is kept as a leaf, so that
+        // rules like `op = "+" | "-"` still say what they matched.
+        let ast = match inner.len() {
+            0 => Ast::Leaf {
+                rule: out.rule.clone(),
+                text: span.text(self.source),
+                span,
+            },
+            1 => inner.pop().unwrap(),
+            _ if self.parser.inline.contains(&out.rule) => return children.extend(inner),
+            _ => Ast::Node {
+                rule: out.rule.clone(),
+                span,
+                children: inner,
+            },
+        };
+        children.push(ast);
+    }
+
+    // Adds what `group`, part of what `out` matched, matched to `children`.
+    fn walk_group(
+        &self,
+        out: &ParseOut<'a>,
+        group: &ParseGrouping<'a>,
+        children: &mut Vec<Ast<'a>>,
+        extent: &mut Option<Span>,
+    ) {
+        match group {
+            ParseGrouping::Terminal(text) | ParseGrouping::Error { skipped: text, .. } => {
+                if text.trim().is_empty() {
+                    return;
+                }
+                cover(extent, out.span);
+                if !(out.literal && is_punctuation(text)) {
+                    children.push(Ast::Leaf {
+                        rule: out.rule.clone(),
+                        text,
+                        span: out.span,
+                    });
+                }
+            }
+            ParseGrouping::Sequence { ts } => {
+                ts.iter().for_each(|t| self.walk(t, children, extent))
+            }
+            ParseGrouping::Optional(Some(group)) => self.walk_group(out, group, children, extent),
+            ParseGrouping::Optional(None) => (),
+            ParseGrouping::Out(out) => self.walk(out, children, extent),
+        }
+    }
+}
+
+fn is_punctuation(text: &str) -> bool {
+    !text.chars().any(char::is_alphanumeric)
+}
+
+fn cover(extent: &mut Option<Span>, span: Span) {
+    *extent = Some(match *extent {
+        Some(e) => Span::new(e.start.min(span.start), e.end.max(span.end)),
+        None => span,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+
+    // The tree as an s-expression of rules and leaf texts.
+    fn shape(grammar: &str, source: &str) -> String {
+        let parser = test_parser(grammar);
+        let ast = parser.parse_ast(source).expect("Test input should parse");
+        show(&ast)
+    }
+
+    fn show(ast: &Ast) -> String {
+        match ast {
+            Ast::Node { rule, children, .. } => {
+                let children: Vec<String> = children.iter().map(show).collect();
+                format!("({rule} {})", children.join(" "))
+            }
+            Ast::Leaf { rule, text, .. } => format!("{rule}:{text}"),
+        }
+    }
+
+    #[test]
+    fn whitespace_punctuation_and_eof_are_left_out() {
+        let grammar = r#"list = "[" (num % ",")? "]" EOF ; @num = (_[0-9])+ ;"#;
+
+        assert_eq!(shape(grammar, " [ 1, 2 ,3 ] "), "(list num:1 num:2 num:3)");
+        assert_eq!(shape(grammar, "[]"), "(list )");
+    }
+
+    #[test]
+    fn only_punctuation_written_as_a_literal_is_left_out() {
+        let grammar = r#"s = "+" [+-] [a-z] "?"? ;"#;
+
+        assert_eq!(shape(grammar, "++a"), "(s s:+ s:a)");
+        assert_eq!(shape(grammar, "+-a?"), "(s s:- s:a)");
+    }
+
+    #[test]
+    fn single_child_chains_collapse() {
+        let grammar = r#"s = a a ; a = b ; b = "(" c ")" ; c = re"[a-z]+" ;"#;
+
+        assert_eq!(shape(grammar, "(x) (y)"), "(s c:x c:y)");
+    }
+
+    #[test]
+    fn rules_that_only_match_punctuation_are_leaves() {
+        let grammar = r#"s = num op num ; op = "+" | "-" ; num = re"[0-9]+" ;"#;
+
+        assert_eq!(shape(grammar, "1 - 2"), "(s num:1 op:- num:2)");
+    }
+
+    #[test]
+    fn inline_rules_give_their_children_to_their_parent() {
+        let grammar = r#"s = pair pair ; -pair = key "=" key ; key = re"[a-z]+" ;"#;
+
+        assert_eq!(shape(grammar, "a=b c=d"), "(s key:a key:b key:c key:d)");
+    }
+
+    #[test]
+    fn the_start_rule_is_always_the_root() {
+        assert_eq!(shape(r#"s = x ; x = "a" ;"#, "a"), "(s x:a)");
+        assert_eq!(shape(r#"s = "(" ")" ;"#, "()"), "(s )");
+    }
+
+    #[test]
+    fn recursive_calls_keep_their_nesting() {
+        let nested = r#"e = "(" e e ")" | "x" ;"#;
+        let left = r#"expr = expr op term | term ; op = "+" | "-" ; term = re"[0-9]+" ;"#;
+
+        assert_eq!(shape(nested, "((x x) x)"), "(e (e e:x e:x) e:x)");
+        assert_eq!(
+            shape(left, "1 + 2 - 3"),
+            "(expr (expr term:1 op:+ term:2) op:- term:3)"
+        );
+    }
+}
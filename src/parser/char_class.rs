@@ -0,0 +1,139 @@
+use super::SharedStr;
+
+// A set of characters, written either as `[a-z_]`, `[^"]` or `'a'..'z'`. It
+// matches a single character, without going through the regex engine.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CharClass {
+    // Inclusive ranges, with single characters as ranges of one.
+    pub ranges: Vec<(char, char)>,
+    pub negated: bool,
+    // The class as it was written, which is how it's shown in errors.
+    pub text: SharedStr,
+}
+
+impl CharClass {
+    // Reads a class from how it's written in a grammar.
+    pub fn parse(text: SharedStr) -> Option<Self> {
+        let (ranges, negated) = match text.strip_prefix('[') {
+            Some(body) => {
+                let body = body.strip_suffix(']')?;
+                match body.strip_prefix('^') {
+                    Some(body) => (parse_ranges(body)?, true),
+                    None => (parse_ranges(body)?, false),
+                }
+            }
+            None => {
+                let (lo, rest) = split_char(text.strip_prefix('\'')?)?;
+                let (hi, rest) = split_char(rest.strip_prefix("'..'")?)?;
+                if rest != "'" {
+                    return None;
+                }
+                (vec![(lo, hi)], false)
+            }
+        };
+
+        Some(CharClass {
+            ranges,
+            negated,
+            text,
+        })
+    }
+
+    pub fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    // Whether any of the ranges runs backwards, like `z-a`.
+    pub fn is_backwards(&self) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo > hi)
+    }
+}
+
+impl std::fmt::Display for CharClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// The character a backslash escape in a grammar stands for.
+pub(crate) fn escaped(c: char) -> char {
+    match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    }
+}
+
+// The items of a class between its brackets. A `-` makes a range unless it's
+// the last thing in the class.
+fn parse_ranges(mut body: &str) -> Option<Vec<(char, char)>> {
+    let mut ranges = vec![];
+    while !body.is_empty() {
+        let (lo, rest) = split_char(body)?;
+        match rest.strip_prefix('-') {
+            Some(after) if !after.is_empty() => {
+                let (hi, rest) = split_char(after)?;
+                ranges.push((lo, hi));
+                body = rest;
+            }
+            _ => {
+                ranges.push((lo, lo));
+                body = rest;
+            }
+        }
+    }
+
+    Some(ranges)
+}
+
+fn split_char(s: &str) -> Option<(char, &str)> {
+    let mut chars = s.chars();
+    let c = match chars.next()? {
+        '\\' => escaped(chars.next()?),
+        c => c,
+    };
+    Some((c, chars.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrammarError, Parser};
+
+    fn class(text: &str) -> CharClass {
+        CharClass::parse(SharedStr::from(text)).expect("Test classes should parse")
+    }
+
+    #[test]
+    fn classes_match_single_characters() {
+        let word = class("[a-z0-9_]");
+        assert!(word.matches('q') && word.matches('7') && word.matches('_'));
+        assert!(!word.matches('A') && !word.matches('-'));
+
+        let not_quote = class(r#"[^"\\]"#);
+        assert!(not_quote.matches('x'));
+        assert!(!not_quote.matches('"') && !not_quote.matches('\\'));
+
+        let range = class("'a'..'f'");
+        assert!(range.matches('c') && !range.matches('g'));
+        assert_eq!(class("[a-]").ranges, [('a', 'a'), ('-', '-')]);
+        assert_eq!(class(r"[\n\]]").ranges, [('\n', '\n'), (']', ']')]);
+    }
+
+    #[test]
+    fn classes_are_grammar_atoms() {
+        let parser = Parser::from_grammar(r#"s = [a-z] ('0'..'9')+ EOF ;"#).unwrap();
+
+        assert!(parser.parse("x42").is_ok());
+        let e = parser.parse("X42").unwrap_err();
+        assert_eq!(e.message(), Some("Expected `[a-z]` here."));
+    }
+
+    #[test]
+    fn backwards_ranges_are_grammar_errors() {
+        let errors = Parser::from_grammar(r#"s = [z-a] ;"#).err().unwrap();
+
+        assert!(matches!(&errors[..], [GrammarError::BackwardsRange { .. }]));
+    }
+}
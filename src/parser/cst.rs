@@ -0,0 +1,391 @@
+use super::{Memo, ParseError, ParseGrouping, ParseOut, Parser, SharedStr, Span, WHITESPACE};
+
+// A lossless concrete syntax tree. Every character of the source is in exactly
+// one token or piece of trivia, so displaying the tree gives back the source
+// it was parsed from.
+#[derive(Debug, Clone)]
+pub enum Cst<'a> {
+    Node(CstNode<'a>),
+    Token(Token<'a>),
+}
+
+// What one call to a rule matched.
+#[derive(Debug, Clone)]
+pub struct CstNode<'a> {
+    pub rule: SharedStr,
+    pub span: Span,
+    pub children: Vec<Cst<'a>>,
+}
+
+// A piece of the source other than trivia. Trivia up to the end of the line
+// the token is on trails it, and the rest leads the next token.
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub rule: SharedStr,
+    pub text: &'a str,
+    pub span: Span,
+    pub leading: Vec<Trivia<'a>>,
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+// Whitespace or a comment, and the rule that matched it.
+#[derive(Debug, Clone)]
+pub struct Trivia<'a> {
+    pub rule: SharedStr,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+impl<'a> Cst<'a> {
+    // Where the tree is in the source, not counting trivia at either end.
+    pub fn span(&self) -> Span {
+        match self {
+            Cst::Node(node) => node.span,
+            Cst::Token(token) => token.span,
+        }
+    }
+
+    // The tree's tokens, in the order they appear.
+    pub fn tokens(&self) -> Vec<&Token<'a>> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'c>(&'c self, tokens: &mut Vec<&'c Token<'a>>) {
+        match self {
+            Cst::Node(node) => node.children.iter().for_each(|c| c.collect_tokens(tokens)),
+            Cst::Token(token) => tokens.push(token),
+        }
+    }
+}
+
+impl std::fmt::Display for Cst<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                f.write_str(trivia.text)?;
+            }
+            f.write_str(token.text)?;
+            for trivia in &token.trailing {
+                f.write_str(trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Parser {
+    // Parses all of `source` into a lossless tree. Unlike `parse`, anything
+    // left over at the end other than trivia is an error.
+    pub fn parse_cst<'a>(&self, source: &'a str) -> Result<Cst<'a>, ParseError<String>> {
+        let mut memo = Memo::default();
+        let out = self.run(source, &mut memo, true)?;
+
+        let mut builder = Builder {
+            source,
+            cursor: 0,
+            events: vec![],
+        };
+        builder.walk(&out, None);
+        let mut end = out.span.end;
+        let rules = &mut vec![self.start.clone()];
+        for trivia in self.skip_trivia(rules, source, &mut end, &mut memo) {
+            builder.trivia(&trivia, &out.rule);
+        }
+
+        Ok(builder.finish(&out))
+    }
+}
+
+enum Event {
+    Open(SharedStr, Span),
+    Close,
+    Token(SharedStr, Span),
+    Trivia(SharedStr, Span),
+}
+
+// Turns a parse tree into a flat list of events, then builds the CST from
+// them once it knows which trivia goes with which token.
+struct Builder<'a> {
+    source: &'a str,
+    cursor: usize,
+    events: Vec<Event>,
+}
+
+impl<'a> Builder<'a> {
+    // Each call to a rule opens a node of its own, including recursive calls
+    // to the rule `out` is part of.
+    fn walk(&mut self, out: &ParseOut<'a>, parent: Option<&SharedStr>) {
+        let start = out.trivia.first().map_or(out.span.start, |t| t.span.start);
+        self.gap(start, parent.unwrap_or(&out.rule));
+        for trivia in &out.trivia {
+            self.trivia(trivia, &out.rule);
+        }
+
+        let opens = out.call;
+        if opens {
+            self.events.push(Event::Open(out.rule.clone(), out.span));
+        }
+        self.walk_group(&out.rule, out.span, &out.out);
+        self.gap(out.span.end, &out.rule);
+        if opens {
+            self.events.push(Event::Close);
+        }
+    }
+
+    fn walk_group(&mut self, rule: &SharedStr, span: Span, group: &ParseGrouping<'a>) {
+        match group {
+            ParseGrouping::Terminal(_) | ParseGrouping::Error { .. } => {
+                self.gap(span.start, rule);
+                if !span.is_empty() && span.start >= self.cursor {
+                    self.events.push(Event::Token(rule.clone(), span));
+                    self.cursor = span.end;
+                }
+            }
+            ParseGrouping::Sequence { ts } => ts.iter().for_each(|t| self.walk(t, Some(rule))),
+            ParseGrouping::Optional(Some(group)) => self.walk_group(rule, span, group),
+            ParseGrouping::Optional(None) => (),
+            ParseGrouping::Out(out) => self.walk(out, Some(rule)),
+        }
+    }
+
+    fn trivia(&mut self, trivia: &ParseOut<'a>, rule: &SharedStr) {
+        self.gap(trivia.span.start, rule);
+        if !trivia.span.is_empty() && trivia.span.start >= self.cursor {
+            self.events
+                .push(Event::Trivia(trivia.rule.clone(), trivia.span));
+            self.cursor = trivia.span.end;
+        }
+    }
+
+    // Text that was matched but isn't in the parse tree, like the separators
+    // of a separated list, becomes a token of its own. Whitespace around it
+    // is trivia.
+    fn gap(&mut self, end: usize, rule: &SharedStr) {
+        if end <= self.cursor {
+            return;
+        }
+
+        let text = &self.source[self.cursor..end];
+        let start = self.cursor + (text.len() - text.trim_start().len());
+        let token_end = end - (text.len() - text.trim_end().len());
+        if start > self.cursor {
+            let span = Span::new(self.cursor, start);
+            self.events.push(Event::Trivia(WHITESPACE.into(), span));
+        }
+        if start < token_end {
+            let span = Span::new(start, token_end);
+            self.events.push(Event::Token(rule.clone(), span));
+            if token_end < end {
+                let span = Span::new(token_end, end);
+                self.events.push(Event::Trivia(WHITESPACE.into(), span));
+            }
+        }
+        self.cursor = end;
+    }
+
+    fn finish(mut self, out: &ParseOut<'a>) -> Cst<'a> {
+        // Trivia needs a token to hold it, even when nothing else matched.
+        if !self.events.iter().any(|e| matches!(e, Event::Token(..))) {
+            let root_end = self.events.iter().rposition(|e| matches!(e, Event::Close));
+            let at = Span::new(self.source.len(), self.source.len());
+            self.events.insert(
+                root_end.unwrap_or(self.events.len()),
+                Event::Token(out.rule.clone(), at),
+            );
+        }
+
+        let mut leading = vec![];
+        let mut trailing: Vec<Vec<Trivia>> = vec![];
+        let mut pending = vec![];
+        for event in &self.events {
+            match event {
+                Event::Trivia(rule, span) => pending.push(Trivia {
+                    rule: rule.clone(),
+                    text: span.text(self.source),
+                    span: *span,
+                }),
+                Event::Token(..) => {
+                    let pending = std::mem::take(&mut pending);
+                    match trailing.last_mut() {
+                        Some(last) => {
+                            let (trails, leads) = split_trivia(pending);
+                            *last = trails;
+                            leading.push(leads);
+                        }
+                        None => leading.push(pending),
+                    }
+                    trailing.push(vec![]);
+                }
+                Event::Open(..) | Event::Close => (),
+            }
+        }
+        if let Some(last) = trailing.last_mut() {
+            *last = pending;
+        }
+
+        let mut leading = leading.into_iter();
+        let mut trailing = trailing.into_iter();
+        let mut stack: Vec<CstNode> = vec![];
+        let mut root = None;
+        for event in self.events {
+            match event {
+                Event::Open(rule, span) => stack.push(CstNode {
+                    rule,
+                    span: Span::new(span.end, span.end),
+                    children: vec![],
+                }),
+                Event::Token(rule, span) => {
+                    let token = Token {
+                        rule,
+                        text: span.text(self.source),
+                        span,
+                        leading: leading.next().unwrap_or_default(),
+                        trailing: trailing.next().unwrap_or_default(),
+                    };
+                    if let Some(node) = stack.last_mut() {
+                        node.children.push(Cst::Token(token));
+                    }
+                }
+                Event::Close => {
+                    let mut node = stack.pop().expect("Nodes should be opened before closing");
+                    if let (Some(first), Some(last)) = (node.children.first(), node.children.last())
+                    {
+                        node.span = Span::new(first.span().start, last.span().end);
+                    }
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Cst::Node(node)),
+                        None => root = Some(node),
+                    }
+                }
+                Event::Trivia(..) => (),
+            }
+        }
+
+        Cst::Node(root.expect("The parse tree should have a root"))
+    }
+}
+
+// Splits the trivia between two tokens into what trails the first, up to and
+// including the end of its line, and what leads the second.
+fn split_trivia(pending: Vec<Trivia>) -> (Vec<Trivia>, Vec<Trivia>) {
+    let mut trailing = vec![];
+    let mut pending = pending.into_iter();
+    while let Some(trivia) = pending.next() {
+        let newline = match trivia.text.find('\n') {
+            Some(newline) => newline,
+            None => {
+                trailing.push(trivia);
+                continue;
+            }
+        };
+
+        // Only whitespace can be split, comments stay whole.
+        let mut leading = vec![];
+        if trivia.text.trim().is_empty() {
+            let split = trivia.span.start + newline + 1;
+            trailing.push(Trivia {
+                rule: trivia.rule.clone(),
+                text: &trivia.text[..=newline],
+                span: Span::new(trivia.span.start, split),
+            });
+            if split < trivia.span.end {
+                leading.push(Trivia {
+                    rule: trivia.rule,
+                    text: &trivia.text[newline + 1..],
+                    span: Span::new(split, trivia.span.end),
+                });
+            }
+        } else {
+            leading.push(trivia);
+        }
+        leading.extend(pending);
+        return (trailing, leading);
+    }
+
+    (trailing, vec![])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+
+    // The tree as an s-expression of rules and token texts.
+    fn shape(cst: &Cst) -> String {
+        match cst {
+            Cst::Node(node) => {
+                let children: Vec<String> = node.children.iter().map(shape).collect();
+                format!("({} {})", node.rule, children.join(" "))
+            }
+            Cst::Token(token) => format!("{:?}", token.text),
+        }
+    }
+
+    #[test]
+    fn displaying_the_tree_gives_back_the_source() {
+        let cases = [
+            (r#"s = ("x")* ;"#, vec!["", "   ", "x\n  x  \n"]),
+            (
+                r#"list = "[" (num % ",")? "]" ; @num = (_[0-9])+ ;"#,
+                vec!["[1, 2 ,3]", " [ ] \n", "[12]\n\n  "],
+            ),
+            (
+                r##"s = (w)+ ; @w = (_[a-z])+ ; WHITESPACE = _[ \n] ; COMMENT = _"#" (_[^\n])* ;"##,
+                vec!["a # one\n  b\n# two\nc #three"],
+            ),
+        ];
+
+        for (grammar, sources) in cases {
+            let parser = test_parser(grammar);
+            for source in sources {
+                let cst = parser.parse_cst(source).unwrap();
+                assert_eq!(cst.to_string(), source, "{grammar}");
+            }
+        }
+    }
+
+    #[test]
+    fn trivia_trails_tokens_to_the_end_of_their_line() {
+        let parser = test_parser(r#"s = ("x")+ ;"#);
+        let cst = parser.parse_cst("  x \n  x ").unwrap();
+        let tokens = cst.tokens();
+        let texts = |trivia: &[Trivia<'_>]| -> Vec<String> {
+            trivia.iter().map(|t| t.text.to_string()).collect()
+        };
+
+        assert_eq!(texts(&tokens[0].leading), ["  "]);
+        assert_eq!(texts(&tokens[0].trailing), [" \n"]);
+        assert_eq!(texts(&tokens[1].leading), ["  "]);
+        assert_eq!(texts(&tokens[1].trailing), [" "]);
+    }
+
+    #[test]
+    fn every_call_is_a_node() {
+        let nested = test_parser(r#"e = "(" e ")" | "x" ;"#);
+        let left =
+            test_parser(r#"expr = expr op term | term ; op = "+" | "-" ; term = re"[0-9]+" ;"#);
+        let called = test_parser(r#"s = x ; x = "a" ;"#);
+
+        assert_eq!(
+            shape(&nested.parse_cst("((x))").unwrap()),
+            r#"(e "(" (e "(" (e "x") ")") ")")"#
+        );
+        assert_eq!(
+            shape(&left.parse_cst("1 + 2 - 3").unwrap()),
+            r#"(expr (expr (expr (term "1")) (op "+") (term "2")) (op "-") (term "3"))"#
+        );
+        assert_eq!(shape(&called.parse_cst("a").unwrap()), r#"(s (x "a"))"#);
+    }
+
+    #[test]
+    fn separators_are_tokens_of_the_list() {
+        let parser = test_parser(r#"list = (num % ",") ; @num = (_[0-9])+ ;"#);
+
+        assert_eq!(
+            shape(&parser.parse_cst("1, 2").unwrap()),
+            r#"(list (num "1") "," (num "2"))"#
+        );
+    }
+}
@@ -1,5 +1,11 @@
 use std::collections::BTreeSet;
 
+use super::{
+    display_width, expand_tabs, Location, ParseGrouping, ParseOut, SharedStr, Span, COMMENT,
+    WHITESPACE,
+};
+
+#[derive(Clone)]
 pub enum Fallible<T, E> {
     Ok(T),
     Recovered(T, E),
@@ -15,171 +21,371 @@ impl<T, E> Fallible<T, E> {
     }
 }
 
-impl<T, E> std::ops::FromResidual for Fallible<T, E> {
-    fn from_residual(residual: <Self as std::ops::Try>::Residual) -> Self {
-        Fallible::Err(residual)
+// Stands in for `?` on a `Fallible`: evaluates to the value, dropping any
+// recovered error, or returns a hard error from the enclosing function.
+macro_rules! propagate {
+    ($e:expr) => {
+        match $e {
+            Fallible::Ok(t) | Fallible::Recovered(t, _) => t,
+            Fallible::Err(e) => return Fallible::Err(e),
+        }
+    };
+}
+pub(crate) use propagate;
+
+#[derive(Clone, Debug)]
+pub enum ParseError<Source> {
+    UnknownNonTerminal(Source),
+    InvalidGrammar(GrammarErrors),
+    BadMatchError(Box<BadMatch<Source>>),
+}
+
+// Where and why the source stopped matching. It's boxed in `ParseError` so
+// that results carrying one stay small.
+#[derive(Clone, Debug)]
+pub struct BadMatch<Source> {
+    pub line: usize,
+    pub col: usize,
+    pub context: Vec<(usize, Source)>,
+    pub idx: usize,
+    pub msg: String,
+    pub terminals: BTreeSet<Source>,
+    pub names: BTreeSet<Source>,
+    pub unexpected: BTreeSet<Source>,
+    pub rules: Vec<Source>,
+    pub captures: Vec<Vec<Span>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum GrammarError {
+    InvalidRegex {
+        pattern: String,
+        msg: String,
+        location: Option<Location>,
+    },
+    BackwardsRange {
+        class: String,
+        location: Option<Location>,
+    },
+    UndefinedRule {
+        rule: String,
+        location: Option<Location>,
+    },
+    DuplicateRule {
+        rule: String,
+        location: Option<Location>,
+        first: Option<Location>,
+    },
+    UnreachableRule {
+        rule: String,
+        location: Option<Location>,
+    },
+    NullableRepetition {
+        rule: String,
+        location: Option<Location>,
+    },
+    InvalidRepeat {
+        rule: String,
+        min: usize,
+        max: usize,
+        location: Option<Location>,
+    },
+    Syntax {
+        msg: String,
+        location: Option<Location>,
+    },
+    Io {
+        path: String,
+        msg: String,
+    },
+}
+
+// Everything wrong with a grammar, as one error.
+#[derive(Clone, Debug, Default)]
+pub struct GrammarErrors(pub Vec<GrammarError>);
+
+impl std::ops::Deref for GrammarErrors {
+    type Target = [GrammarError];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
 
-impl<T, E> std::ops::Try for Fallible<T, E> {
-    type Output = T;
-    type Residual = E;
+impl From<GrammarError> for GrammarErrors {
+    fn from(e: GrammarError) -> Self {
+        GrammarErrors(vec![e])
+    }
+}
 
-    fn from_output(output: Self::Output) -> Self {
-        Fallible::Ok(output)
+impl GrammarError {
+    pub fn is_warning(&self) -> bool {
+        matches!(self, GrammarError::UnreachableRule { .. })
     }
 
-    fn branch(self) -> std::ops::ControlFlow<Self::Residual, Self::Output> {
+    pub fn location(&self) -> Option<Location> {
         match self {
-            Fallible::Ok(t) | Fallible::Recovered(t, _) => std::ops::ControlFlow::Continue(t),
-            Fallible::Err(e) => std::ops::ControlFlow::Break(e),
+            GrammarError::InvalidRegex { location, .. }
+            | GrammarError::BackwardsRange { location, .. }
+            | GrammarError::UndefinedRule { location, .. }
+            | GrammarError::DuplicateRule { location, .. }
+            | GrammarError::UnreachableRule { location, .. }
+            | GrammarError::NullableRepetition { location, .. }
+            | GrammarError::InvalidRepeat { location, .. }
+            | GrammarError::Syntax { location, .. } => *location,
+            GrammarError::Io { .. } => None,
         }
     }
 }
 
-#[derive(Clone)]
-pub enum ParseError<Source> {
-    UnknownNonTerminal(Source),
-    BadMatchError {
-        line: Source,
-        col: usize,
-        idx: usize,
-        msg: String,
-        terminals: BTreeSet<Source>,
-        rules: Vec<Source>,
-    },
+impl<Source> ParseError<Source> {
+    // The byte offset into the source that the error was raised at.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ParseError::BadMatchError(e) => Some(e.idx),
+            _ => None,
+        }
+    }
+
+    // The line and column of the error, once it has been located.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            ParseError::BadMatchError(e) if e.line > 0 => Some(Location {
+                line: e.line,
+                col: e.col,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            ParseError::BadMatchError(e) => Some(&e.msg),
+            _ => None,
+        }
+    }
+
+    // The terminals that would have let parsing continue.
+    pub fn expected(&self) -> Vec<&Source> {
+        match self {
+            ParseError::BadMatchError(e) => e.terminals.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    // The display names of the rules that would have let parsing continue.
+    pub fn expected_names(&self) -> Vec<&Source> {
+        match self {
+            ParseError::BadMatchError(e) => e.names.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    // The rules being parsed when the error was raised, outermost first.
+    pub fn rules(&self) -> &[Source] {
+        match self {
+            ParseError::BadMatchError(e) => &e.rules,
+            _ => &[],
+        }
+    }
+
+    pub fn grammar_errors(&self) -> &[GrammarError] {
+        match self {
+            ParseError::InvalidGrammar(errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+impl ParseError<String> {
+    // Line information is only worked out for the error that is finally
+    // reported, along with `context_lines` lines either side of it.
+    pub fn locate(&mut self, source: &str, context_lines: usize) {
+        if let ParseError::BadMatchError(e) = self {
+            let location = Location::new(source, e.idx);
+            e.line = location.line;
+            e.col = location.col;
+            e.context = source
+                .split('\n')
+                .enumerate()
+                .skip(location.line.saturating_sub(context_lines + 1))
+                .take(location.line.min(context_lines + 1) + context_lines)
+                .map(|(i, text)| (i + 1, String::from(text.strip_suffix('\r').unwrap_or(text))))
+                .collect();
+        }
+    }
 }
 
-impl<'a> ParseError<&'a str> {
+impl ParseError<SharedStr> {
     pub fn new_bad_match<S: Into<String>>(
-        source: &'a str,
         idx: &usize,
         msg: S,
-        terminals: Vec<&'a str>,
-        rules: Vec<&'a str>,
-    ) -> ParseError<&'a str> {
-        let prev_newline = source[0..*idx].rfind("\n");
-        let next_newline = source[*idx..].find("\n");
-        let (line, col) = match (prev_newline, next_newline) {
-            (Some(prev), Some(next)) => {
-                if prev + 1 < *idx {
-                    (&source[prev + 1..*idx + next], *idx - prev - 1)
-                } else {
-                    (&source[0..*idx + next], *idx)
-                }
-            }
-            (None, Some(next)) => (&source[0..*idx + next], *idx),
-            (Some(prev), None) => {
-                if prev + 1 < *idx {
-                    (&source[prev + 1..], *idx - prev - 1)
-                } else {
-                    (&source[0..*idx], *idx)
-                }
-            }
-            (None, None) => (source, *idx),
-        };
-
-        ParseError::BadMatchError {
-            line,
-            col,
+        terminals: Vec<SharedStr>,
+        rules: Vec<SharedStr>,
+    ) -> ParseError<SharedStr> {
+        ParseError::BadMatchError(Box::new(BadMatch {
+            line: 0,
+            col: 0,
+            context: vec![],
             idx: *idx,
             msg: msg.into(),
             terminals: BTreeSet::from_iter(terminals),
-            rules: rules,
+            names: BTreeSet::new(),
+            unexpected: BTreeSet::new(),
+            captures: vec![vec![]; rules.len()],
+            rules,
+        }))
+    }
+
+    // Reports a rule that failed without matching anything by its display
+    // name, rather than by whatever terminal inside it failed first. The
+    // error then belongs to the rule that called it.
+    pub fn expect_rule(&mut self, name: &SharedStr, caller: &[SharedStr]) {
+        if let ParseError::BadMatchError(e) = self {
+            e.msg = format!("Expected {name} here.");
+            e.terminals.clear();
+            e.unexpected.clear();
+            e.names = BTreeSet::from([name.clone()]);
+            e.rules.truncate(caller.len());
+            e.captures.truncate(caller.len());
+        }
+    }
+
+    // Records what the innermost rule in `rules` had matched before failing,
+    // ahead of anything an inner sequence of the same rule already recorded.
+    pub fn capture(&mut self, rules: &[SharedStr], outs: &[ParseOut]) {
+        let depth = rules.len() - 1;
+        if let ParseError::BadMatchError(e) = self {
+            if e.rules.get(depth) == rules.last() {
+                let mut spans = vec![];
+                outs.iter().for_each(|out| out.collect_captures(&mut spans));
+                e.captures[depth].splice(0..0, spans);
+            }
+        }
+    }
+
+    pub fn new_expected_end(idx: &usize, rules: Vec<SharedStr>) -> ParseError<SharedStr> {
+        let mut e = ParseError::new_bad_match(idx, "Expected end of input here.", vec![], rules);
+        if let ParseError::BadMatchError(e) = &mut e {
+            e.names.insert(SharedStr::from("end of input"));
+        }
+
+        e
+    }
+
+    pub fn new_unexpected(
+        source: &str,
+        idx: &usize,
+        found: &str,
+        rules: Vec<SharedStr>,
+    ) -> ParseError<SharedStr> {
+        let found = if found.is_empty() && *idx == source.len() {
+            "EOF"
+        } else {
+            found
+        };
+        let mut e =
+            ParseError::new_bad_match(idx, format!("Unexpected `{found}` here."), vec![], rules);
+        if let ParseError::BadMatchError(e) = &mut e {
+            e.unexpected.insert(SharedStr::from(found));
         }
+
+        e
     }
 
     pub fn collect_furthest(
-        errors: Vec<ParseError<&'a str>>,
-    ) -> Fallible<Option<ParseError<&'a str>>, ParseError<&'a str>> {
+        errors: Vec<ParseError<SharedStr>>,
+    ) -> Fallible<Option<ParseError<SharedStr>>, ParseError<SharedStr>> {
         if errors.is_empty() {
             return Fallible::Ok(None);
         }
 
-        let mut sizes = vec![];
-        for e in &errors {
-            match e {
-                ParseError::UnknownNonTerminal(_) => return Fallible::Err(e.clone()),
-                ParseError::BadMatchError { idx, .. } => sizes.push(idx),
+        if let Some(e) = errors.iter().find(|e| e.offset().is_none()) {
+            return Fallible::Err(e.clone());
+        }
+
+        // Whitespace and comments can go almost anywhere, so they're only
+        // what was expected when nothing else was.
+        let in_trivia = |e: &ParseError<SharedStr>| match e {
+            ParseError::BadMatchError(e) => e
+                .rules
+                .iter()
+                .any(|rule| rule == WHITESPACE || rule == COMMENT),
+            _ => false,
+        };
+        let errors = if errors.iter().all(in_trivia) {
+            errors
+        } else {
+            errors.into_iter().filter(|e| !in_trivia(e)).collect()
+        };
+
+        let max = errors.iter().filter_map(|e| e.offset()).max().unwrap();
+
+        let at_max = errors.iter().filter(|e| e.offset() == Some(max));
+        let (mut terminals, mut names, mut unexpected) = (vec![], vec![], vec![]);
+        for e in at_max {
+            if let ParseError::BadMatchError(e) = e {
+                terminals.extend(e.terminals.iter().cloned());
+                names.extend(e.names.iter().cloned());
+                unexpected.extend(e.unexpected.iter().cloned());
             }
         }
 
-        let max = errors
-            .iter()
-            .map(|e| match e {
-                ParseError::UnknownNonTerminal(_) => unreachable!(),
-                ParseError::BadMatchError { idx, .. } => *idx,
-            })
-            .max()
-            .unwrap();
-
-        let terminals: Vec<&str> = errors
+        let (names, terminals) = (BTreeSet::from_iter(names), BTreeSet::from_iter(terminals));
+        let mut expected: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        expected.extend(terminals.iter().map(|t| format!("`{t}`")));
+
+        let first = errors
             .iter()
-            .map(|e| match e {
-                ParseError::UnknownNonTerminal(_) => unreachable!(),
-                ParseError::BadMatchError { terminals, idx, .. } => (terminals, idx),
-            })
-            .filter_map(|(terms, idx)| if *idx == max { Some(terms) } else { None })
-            .flatten()
-            .map(|s| *s)
-            .collect();
-
-        let msg = match terminals.len() {
-            0 => return Fallible::Ok(None),
-            1 => format!("Expected `{}` here.", terminals[0]),
-            _ => {
-                let mut msg = String::from("Expected one of ");
-                for t in &terminals[0..terminals.len() - 1] {
-                    msg = format!("{msg}`{t}`, ")
-                }
-                format!("{msg} or `{}`.", terminals.last().unwrap())
-            }
+            .find(|e| e.offset() == Some(max))
+            .expect("Some error should be at the furthest offset");
+        let msg = match (expected.as_slice(), unexpected.first()) {
+            ([], Some(found)) => format!("Unexpected `{found}` here."),
+            ([], None) => String::from(first.message().unwrap_or_default()),
+            ([one], _) => format!("Expected {one} here."),
+            ([rest @ .., last], _) => format!("Expected one of {} or {last}.", rest.join(", ")),
         };
 
-        match errors.iter().find(|e| match e {
-            ParseError::UnknownNonTerminal(_) => unreachable!(),
-            ParseError::BadMatchError { idx, .. } => *idx == max,
-        }) {
-            Some(ParseError::BadMatchError {
-                line,
-                col,
-                idx,
-                rules,
-                ..
-            }) => Fallible::Ok(Some(ParseError::BadMatchError {
-                line,
-                col: *col,
-                idx: *idx,
-                msg,
-                terminals: BTreeSet::from_iter(terminals),
-                rules: rules.clone(),
-            })),
+        match first {
+            ParseError::BadMatchError(e) => {
+                Fallible::Ok(Some(ParseError::BadMatchError(Box::new(BadMatch {
+                    line: e.line,
+                    col: e.col,
+                    context: e.context.clone(),
+                    idx: e.idx,
+                    msg,
+                    terminals,
+                    names,
+                    unexpected: BTreeSet::from_iter(unexpected),
+                    rules: e.rules.clone(),
+                    captures: e.captures.clone(),
+                }))))
+            }
             _ => unreachable!(),
         }
     }
 }
 
-impl<Source> std::fmt::Debug for ParseError<Source>
-where
-    Source: std::fmt::Display + std::fmt::Debug,
-{
+impl<Source: std::fmt::Display> std::fmt::Display for ParseError<Source> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::UnknownNonTerminal(non_term) => {
                 write!(f, "Grammar Error - Unknown rule: `{non_term}`")
             }
-            Self::BadMatchError { line, col, msg, .. } => {
-                writeln!(f, "{}", msg)?;
-                writeln!(f, "{line}")?;
-                match col.checked_sub(1) {
-                    Some(n) => {
-                        for _ in 0..n {
-                            write!(f, " ")?;
-                        }
+            Self::InvalidGrammar(errors) => write!(f, "{errors}"),
+            Self::BadMatchError(e) => {
+                write!(f, "{}", e.msg)?;
+
+                let width = e.context.last().map_or(0, |(n, _)| n.to_string().len());
+                for (n, text) in &e.context {
+                    let text = text.to_string();
+                    let gutter = format!("[line {n:>width$}]: ");
+                    write!(f, "\n{gutter}{}", expand_tabs(&text))?;
+                    if *n == e.line {
+                        let offset = display_width(&text, e.col - 1);
+                        write!(f, "\n{}^", " ".repeat(gutter.len() + offset))?;
                     }
-                    None => (),
-                };
-                write!(f, "^")?;
+                }
 
                 Ok(())
             }
@@ -187,28 +393,245 @@ where
     }
 }
 
-impl<'a> From<ParseError<&'a str>> for ParseError<String> {
-    fn from(value: ParseError<&'a str>) -> Self {
+impl<Source: std::fmt::Display + std::fmt::Debug> std::error::Error for ParseError<Source> {}
+
+impl std::fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_warning() {
+            write!(f, "Grammar Warning - ")?;
+        } else {
+            write!(f, "Grammar Error - ")?;
+        }
+        if let Some(location) = self.location() {
+            write!(f, "{location}: ")?;
+        }
+
+        match self {
+            Self::InvalidRegex { pattern, .. } => write!(f, "Invalid regex `{pattern}`")?,
+            Self::BackwardsRange { class, .. } => write!(
+                f,
+                "Character class `{class}` has a range that runs backwards"
+            )?,
+            Self::UndefinedRule { rule, .. } => write!(f, "Unknown rule: `{rule}`")?,
+            Self::DuplicateRule { rule, first, .. } => {
+                write!(f, "Rule `{rule}` is defined more than once")?;
+                if let Some(first) = first {
+                    write!(f, " (first defined at {first})")?;
+                }
+            }
+            Self::UnreachableRule { rule, .. } => write!(f, "Rule `{rule}` is never used")?,
+            Self::NullableRepetition { rule, .. } => write!(
+                f,
+                "Repetition in rule `{rule}` can match without consuming input"
+            )?,
+            Self::InvalidRepeat { rule, min, max, .. } => write!(
+                f,
+                "Repetition in rule `{rule}` allows at most {max}, but needs at least {min}"
+            )?,
+            Self::Syntax { msg, .. } => write!(f, "{msg}")?,
+            Self::Io { path, msg } => write!(f, "Couldn't read `{path}`: {msg}")?,
+        }
+
+        if let Self::InvalidRegex { msg, .. } = self {
+            write!(f, ":\n{msg}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for GrammarError {}
+
+// One error per line.
+impl std::fmt::Display for GrammarErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, e) in self.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{e}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for GrammarErrors {}
+
+// A grammar that fails to parse is reported where the grammar parser stopped.
+impl From<ParseError<String>> for GrammarError {
+    fn from(e: ParseError<String>) -> Self {
+        match e {
+            ParseError::InvalidGrammar(mut errors) if errors.len() == 1 => errors.0.remove(0),
+            e => GrammarError::Syntax {
+                msg: e.message().map_or_else(|| e.to_string(), String::from),
+                location: e.location(),
+            },
+        }
+    }
+}
+
+impl<Source> From<GrammarErrors> for ParseError<Source> {
+    fn from(errors: GrammarErrors) -> Self {
+        ParseError::InvalidGrammar(errors)
+    }
+}
+
+impl From<ParseError<SharedStr>> for ParseError<String> {
+    fn from(value: ParseError<SharedStr>) -> Self {
         match value {
-            ParseError::BadMatchError {
-                line,
-                col,
-                idx,
-                msg,
-                terminals,
-                rules,
-            } => ParseError::BadMatchError {
-                line: String::from(line),
-                col: col,
-                idx: idx,
-                msg: msg,
-                terminals: terminals
+            ParseError::BadMatchError(e) => ParseError::BadMatchError(Box::new(BadMatch {
+                line: e.line,
+                col: e.col,
+                context: e
+                    .context
                     .into_iter()
-                    .map(|term| String::from(term))
+                    .map(|(n, text)| (n, text.to_string()))
                     .collect(),
-                rules: rules.into_iter().map(|rule| String::from(rule)).collect(),
-            },
-            ParseError::UnknownNonTerminal(e) => ParseError::UnknownNonTerminal(String::from(e)),
+                idx: e.idx,
+                msg: e.msg,
+                terminals: e.terminals.iter().map(ToString::to_string).collect(),
+                names: e.names.iter().map(ToString::to_string).collect(),
+                unexpected: e.unexpected.iter().map(ToString::to_string).collect(),
+                rules: e.rules.iter().map(ToString::to_string).collect(),
+                captures: e.captures,
+            })),
+            ParseError::UnknownNonTerminal(e) => ParseError::UnknownNonTerminal(e.to_string()),
+            ParseError::InvalidGrammar(errors) => ParseError::InvalidGrammar(errors),
         }
     }
 }
+
+impl ParseOut<'_> {
+    // Calls into other rules, or recursively into the same one, are captured
+    // whole; text matched directly is captured piece by piece, skipping
+    // implicit whitespace.
+    fn collect_captures(&self, spans: &mut Vec<Span>) {
+        if self.call {
+            spans.push(self.span);
+            return;
+        }
+
+        match &self.out {
+            ParseGrouping::Terminal(t) if t.trim().is_empty() => (),
+            ParseGrouping::Terminal(_) | ParseGrouping::Optional(Some(_)) => spans.push(self.span),
+            ParseGrouping::Optional(None) => (),
+            ParseGrouping::Error { .. } => spans.push(self.span),
+            ParseGrouping::Sequence { ts } => ts.iter().for_each(|t| t.collect_captures(spans)),
+            ParseGrouping::Out(out) => out.collect_captures(spans),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+    use crate::Parser;
+
+    #[test]
+    fn errors_show_their_line_with_context() {
+        let mut parser = test_parser(r#"s = (re"[a-zé]+")+ ";" ;"#);
+        parser.context_lines = 1;
+        let e = parser.parse("aé\nbé\té!\nc\nd").unwrap_err();
+
+        let location = e.location().unwrap();
+        assert_eq!((location.line, location.col), (2, 5));
+        assert_eq!(
+            e.to_string(),
+            "Expected one of `;` or `[a-zé]+`.\n\
+             [line 1]: aé\n\
+             [line 2]: bé  é!\n               ^\n\
+             [line 3]: c"
+        );
+    }
+
+    #[test]
+    fn accessors_describe_the_failure() {
+        let parser = test_parser(r#"s = "a" t ; t = "b" | "c" ;"#);
+        let e = parser.parse("a d").unwrap_err();
+
+        assert_eq!(e.offset(), Some(2));
+        assert_eq!(e.expected(), ["b", "c"]);
+        assert_eq!(e.rules(), ["s", "t"]);
+        assert_eq!(e.message(), Some("Expected one of `b` or `c`."));
+        assert!(e.grammar_errors().is_empty());
+    }
+
+    fn load(grammar: &str) -> Result<Parser, ParseError<String>> {
+        let out = Parser::grammar_parser().parse(grammar)?;
+        Ok(crate::grammar_into_parser(grammar, out)?)
+    }
+
+    #[test]
+    fn grammar_errors_convert_to_parse_errors() {
+        let e = load("s = t ;").err().unwrap();
+
+        assert!(matches!(
+            e.grammar_errors(),
+            [GrammarError::UndefinedRule { .. }]
+        ));
+        assert_eq!(
+            e.to_string(),
+            "Grammar Error - line 1, column 5: Unknown rule: `t`"
+        );
+        let boxed: Box<dyn std::error::Error> = Box::new(e);
+        assert!(boxed.to_string().contains("`t`"));
+    }
+
+    fn message(grammar: &str, source: &str) -> String {
+        let parser = test_parser(grammar);
+        let e = parser
+            .parse(source)
+            .expect_err("Test input shouldn't parse");
+        e.message().unwrap().to_string()
+    }
+
+    #[test]
+    fn rules_that_fail_whole_are_expected_by_name() {
+        let grammar = r#"
+            s = "(" (ident | num) ")" ;
+            @ident "identifier" = re"[a-z]" (_re"[a-z0-9]")* ;
+            num = re"[0-9]+" ;
+        "#;
+
+        assert_eq!(
+            message(grammar, "( ;"),
+            "Expected one of identifier or `[0-9]+`."
+        );
+        assert_eq!(message(grammar, "(a ;"), "Expected `)` here.");
+    }
+
+    #[test]
+    fn trivia_is_never_expected() {
+        let comments = r##"s = "a" "b" ; COMMENT = _"#" (_[a-z])* ;"##;
+
+        assert_eq!(message(r#"s = "a" "b" ;"#, "a ;"), "Expected `b` here.");
+        assert_eq!(message(comments, "a ;"), "Expected `b` here.");
+    }
+
+    fn double(input: Fallible<u32, &'static str>) -> Fallible<u32, &'static str> {
+        let n = propagate!(input);
+        Fallible::Ok(n * 2)
+    }
+
+    #[test]
+    fn propagate_returns_only_hard_errors() {
+        assert!(matches!(double(Fallible::Ok(2)), Fallible::Ok(4)));
+        assert!(matches!(
+            double(Fallible::Recovered(2, "skipped")),
+            Fallible::Ok(4)
+        ));
+        assert!(matches!(
+            double(Fallible::Err("failed")),
+            Fallible::Err("failed")
+        ));
+    }
+
+    #[test]
+    fn recovered_values_are_kept_as_results() {
+        assert_eq!(Fallible::<u32, ()>::Ok(1).into_result(), Ok(1));
+        assert_eq!(Fallible::Recovered(1, ()).into_result(), Ok(1));
+        assert_eq!(Fallible::<u32, ()>::Err(()).into_result(), Err(()));
+    }
+}
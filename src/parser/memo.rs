@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use super::{Fallible, ParseError, ParseOut, SharedStr};
+
+type MemoResult<'a> = Fallible<ParseOut<'a>, ParseError<SharedStr>>;
+
+struct MemoEntry<'a> {
+    result: MemoResult<'a>,
+    end: usize,
+    depth: usize,
+}
+
+// Entries are keyed by rule and position, and by whether trivia was being
+// skipped, as rules don't skip trivia inside trivia and so can match
+// differently there.
+#[derive(Default)]
+pub struct Memo<'a> {
+    entries: HashMap<(SharedStr, usize, bool), MemoEntry<'a>>,
+    growing: Vec<usize>,
+    recover: bool,
+    skipping_trivia: bool,
+    partial: Option<ParseOut<'a>>,
+}
+
+impl<'a> Memo<'a> {
+    pub fn recovering() -> Self {
+        Memo {
+            recover: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_recovering(&self) -> bool {
+        self.recover
+    }
+
+    // What the expression that last failed had matched before it did. It's
+    // only kept when recovering, so that a failure that can't be recovered
+    // from still leaves a tree of what came before it.
+    pub fn set_partial(&mut self, partial: Option<ParseOut<'a>>) {
+        if self.recover {
+            self.partial = partial;
+        }
+    }
+
+    pub fn take_partial(&mut self) -> Option<ParseOut<'a>> {
+        self.partial.take()
+    }
+
+    // Trivia rules, and whatever they call, don't skip trivia themselves.
+    pub fn is_skipping_trivia(&self) -> bool {
+        self.skipping_trivia
+    }
+
+    pub fn set_skipping_trivia(&mut self, skipping: bool) {
+        self.skipping_trivia = skipping;
+    }
+
+    pub fn get(
+        &self,
+        rule: &SharedStr,
+        rules: &[SharedStr],
+        idx: &mut usize,
+    ) -> Option<MemoResult<'a>> {
+        let entry = self.entries.get(&self.key(rule, *idx))?;
+        *idx = entry.end;
+
+        // Errors carry the rule stack they were raised under, so swap the
+        // stack from the original call site for the current one.
+        let rebase = |e: &ParseError<SharedStr>| {
+            let mut e = e.clone();
+            if let ParseError::BadMatchError(e) = &mut e {
+                e.rules = rules
+                    .iter()
+                    .chain(e.rules.iter().skip(entry.depth))
+                    .cloned()
+                    .collect();
+                // Captures for the caller's rules are added as the error
+                // travels back up through them.
+                e.captures.splice(0..entry.depth, vec![vec![]; rules.len()]);
+            }
+            e
+        };
+
+        Some(match &entry.result {
+            Fallible::Ok(out) => Fallible::Ok(out.clone()),
+            Fallible::Recovered(out, e) => Fallible::Recovered(out.clone(), rebase(e)),
+            Fallible::Err(e) => Fallible::Err(rebase(e)),
+        })
+    }
+
+    pub fn insert(
+        &mut self,
+        rule: &SharedStr,
+        rules: &[SharedStr],
+        start: usize,
+        end: usize,
+        result: &MemoResult<'a>,
+    ) {
+        self.entries.insert(
+            self.key(rule, start),
+            MemoEntry {
+                result: result.clone(),
+                end,
+                depth: rules.len(),
+            },
+        );
+    }
+
+    pub fn remove(&mut self, rule: &SharedStr, start: usize) {
+        self.entries.remove(&self.key(rule, start));
+    }
+
+    fn key(&self, rule: &SharedStr, idx: usize) -> (SharedStr, usize, bool) {
+        (rule.clone(), idx, self.skipping_trivia)
+    }
+
+    pub fn begin_growing(&mut self, start: usize) {
+        self.growing.push(start);
+    }
+
+    pub fn end_growing(&mut self) {
+        self.growing.pop();
+    }
+
+    // Results at a position where a left recursive seed is still growing
+    // depend on that seed, so they must not outlive the current iteration.
+    pub fn is_growing(&self, start: usize) -> bool {
+        self.growing.contains(&start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+    use crate::{AtomicExpr, ParseGrouping};
+    use std::sync::Arc;
+
+    // Parses each source with memoization off and then on, and compares the
+    // trees or errors.
+    fn assert_same(grammar: &str, sources: &[&str]) {
+        let mut parser = test_parser(grammar);
+        parser.consume_all = true;
+        for source in sources {
+            parser.memoize = false;
+            let plain = format!("{:?}", parser.parse(source));
+            parser.memoize = true;
+            let memoized = format!("{:?}", parser.parse(source));
+
+            assert_eq!(plain, memoized, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn memoization_doesnt_change_results() {
+        let grammar = r#"
+            expr   = (term "+" expr) | (term "-" expr) | term ;
+            term   = (atom "*" term) | atom ;
+            atom   = ("(" expr ")") | num ;
+            @num "number" = (_[0-9])+ ;
+        "#;
+
+        assert_same(
+            grammar,
+            &["1", "1 + 2 * 3", "((1) - (2 * (3)))", "(1 +", "1 2", ""],
+        );
+    }
+
+    #[test]
+    fn errors_keep_the_rules_of_the_call_that_hit_the_memo() {
+        let grammar = r#"s = (a "x") | (b "y") ; a = n ; b = n ; n = "(" n ")" | "z" ;"#;
+
+        assert_same(grammar, &["((z))y", "((z))q", "((z)"]);
+    }
+
+    // Copying a deep tree out of the memo on every hit makes parsing nested
+    // input quadratic, so hits share the tree instead.
+    #[test]
+    fn hits_share_the_tree_they_return() {
+        let parser = test_parser(r#"s = ("(" s ")") | "x" ;"#);
+        let rule = SharedStr::from("s");
+        let mut memo = Memo::default();
+        let mut end = 0;
+        let result = AtomicExpr::NonTerminal(rule.clone()).parse(
+            &mut vec![],
+            &parser,
+            "((x))",
+            &mut end,
+            &mut memo,
+        );
+        memo.insert(&rule, &[], 0, end, &result);
+
+        let hit = || memo.get(&rule, &[], &mut 0).unwrap().into_result().unwrap();
+        match (hit().out, hit().out) {
+            (ParseGrouping::Out(a), ParseGrouping::Out(b)) => assert!(Arc::ptr_eq(&a, &b)),
+            other => panic!("Expected both hits to be calls, got {other:?}"),
+        }
+    }
+}
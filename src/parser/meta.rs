@@ -0,0 +1,242 @@
+use std::sync::OnceLock;
+
+use super::{AtomicExpr, ParseExpr, Parser, COMMENT};
+
+// The grammar language, written in itself. Nothing is skipped implicitly, so
+// every atom is marked with `_` and whitespace is spelled out.
+//
+// This is read by the bootstrap parser, which only knows the syntax used
+// here, so new syntax can be added to the language but not used in it. The
+// display names are what syntax errors in grammars expect.
+pub const META_GRAMMAR: &str = r#"
+    grammar      = (_rule)+ _ws _EOF ;
+    rule         = _ws (_"-")? (_"@")? _non_terminal (_ws _STRING)? _ws _"=" _ws _choice _ws _";" _ws ;
+    choice       = (_sequence _ws _"|" _ws _choice) | _sequence ;
+    sequence     = (_modifier _sep _sequence) | _modifier ;
+    modifier "expression"
+                 = (_"&" _modifier)
+                 | (_"!" _modifier)
+                 | (_"_" _primary)
+                 | (_primary _"+")
+                 | (_primary _"*")
+                 | (_primary _"?")
+                 | (_primary _bounds)
+                 | (_primary _ws _"~" _ws _primary)
+                 | (_primary _ws _"%%" _ws _primary)
+                 | (_primary _ws _"%" _ws _primary)
+                 | _primary ;
+    bounds       = _"{" _ws _count _ws (_"," _ws (_count _ws)?)? _"}" ;
+    @count "number"
+                 = (_DIGIT)+ ;
+    primary      = (_"(" _ws _choice _ws _")") | _atomic ;
+    atomic       = _terminal | _regex | _class | _range | _non_terminal ;
+    terminal     = _STRING ;
+    @non_terminal "rule name"
+                 = _ALPHA (_ALPHA | _DIGIT)* ;
+    regex        = _"re" _STRING ;
+    @STRING "string"
+                 = _"\"" (_escape | _char)* _"\"" ;
+    escape "escape sequence"
+                 = _"\\" _re"\S" ;
+    char "character"
+                 = _[^\\"] ;
+    @class "character class"
+                 = _"[" (_"^")? (_class_char (_"-" _class_char)?)+ _"]" ;
+    class_char "character"
+                 = _escape | _[^\\\]] ;
+    @range "character range"
+                 = _CHAR _".." _CHAR ;
+    CHAR "quoted character"
+                 = _"'" (_escape | _[^\\']) _"'" ;
+    ALPHA "letter"
+                 = _[a-zA-Z_] ;
+    DIGIT "digit"
+                 = _[0-9] ;
+
+    // Whitespace and comments can go between any two tokens, and `sep` is
+    // what separates the items of a sequence.
+    ws           = (_re"\s+" | _COMMENT)* ;
+    sep          = (_re"\s+" | _COMMENT)+ ;
+    @COMMENT     = _re"//[^\n]*"                  // Line comments
+                 | _re"/\*([^*]|\*+[^*/])*\*+/" ; /* and block comments */
+"#;
+
+impl Parser {
+    // The parser for grammars, built from `META_GRAMMAR` the first time it's
+    // needed.
+    pub fn grammar_parser() -> &'static Parser {
+        static GRAMMAR_PARSER: OnceLock<Parser> = OnceLock::new();
+        GRAMMAR_PARSER.get_or_init(|| {
+            let out = Parser::bootstrap_parser()
+                .parse(META_GRAMMAR)
+                .expect("The meta grammar should parse");
+            let mut parser = crate::grammar_into_parser(META_GRAMMAR, out)
+                .expect("The meta grammar should be valid");
+            parser.memoize = true;
+            parser
+        })
+    }
+
+    // A parser for just enough of the grammar language to read
+    // `META_GRAMMAR`. Its rules have the same names as the ones there, so
+    // what it parses is lowered the same way.
+    fn bootstrap_parser() -> Self {
+        let t = |term: &str| ParseExpr::Atomic(AtomicExpr::Terminal(term.into()));
+        let re = |re_str: &str| ParseExpr::Atomic(AtomicExpr::Regex(re_str.into()));
+        let n = |non_term: &str| ParseExpr::Atomic(AtomicExpr::NonTerminal(non_term.into()));
+        let seq = |es: Vec<ParseExpr>| ParseExpr::Sequence { es };
+        let choice = |es: Vec<ParseExpr>| ParseExpr::Choice { es };
+        let opt = |e: ParseExpr| ParseExpr::Optional { e: Box::new(e) };
+        let star = |e: ParseExpr| ParseExpr::ZeroOrMore { e: Box::new(e) };
+        let plus = |e: ParseExpr| ParseExpr::OneOrMore { e: Box::new(e) };
+
+        let rules = [
+            (
+                "grammar",
+                seq(vec![
+                    plus(n("rule")),
+                    n("ws"),
+                    ParseExpr::Atomic(AtomicExpr::EndOfFile),
+                ]),
+            ),
+            (
+                "rule",
+                seq(vec![
+                    n("ws"),
+                    opt(t("@")),
+                    n("non_terminal"),
+                    opt(seq(vec![n("ws"), n("STRING")])),
+                    n("ws"),
+                    t("="),
+                    n("ws"),
+                    n("choice"),
+                    n("ws"),
+                    t(";"),
+                    n("ws"),
+                ]),
+            ),
+            (
+                "choice",
+                seq(vec![
+                    n("sequence"),
+                    star(seq(vec![n("ws"), t("|"), n("ws"), n("sequence")])),
+                ]),
+            ),
+            (
+                "sequence",
+                seq(vec![
+                    n("modifier"),
+                    star(seq(vec![n("sep"), n("modifier")])),
+                ]),
+            ),
+            (
+                "modifier",
+                choice(vec![
+                    seq(vec![t("_"), n("primary")]),
+                    seq(vec![n("primary"), opt(re("[+*?]"))]),
+                ]),
+            ),
+            (
+                "primary",
+                choice(vec![
+                    seq(vec![t("("), n("ws"), n("choice"), n("ws"), t(")")]),
+                    n("atomic"),
+                ]),
+            ),
+            (
+                "atomic",
+                choice(vec![
+                    n("terminal"),
+                    n("regex"),
+                    n("class"),
+                    n("non_terminal"),
+                ]),
+            ),
+            ("terminal", n("STRING")),
+            ("regex", seq(vec![t("re"), n("STRING")])),
+            ("STRING", re(r#""(\\.|[^\\"])*""#)),
+            ("class", re(r"\[(\\.|[^\\\]])*\]")),
+            ("non_terminal", re("[a-zA-Z_][a-zA-Z_0-9]*")),
+            ("ws", star(choice(vec![re(r"\s+"), n(COMMENT)]))),
+            ("sep", plus(choice(vec![re(r"\s+"), n(COMMENT)]))),
+            (
+                COMMENT,
+                choice(vec![re(r"//[^\n]*"), re(r"/\*([^*]|\*+[^*/])*\*+/")]),
+            ),
+        ];
+
+        let rules = rules
+            .into_iter()
+            .map(|(id, rule)| (id.into(), (rule, false)))
+            .collect();
+        Parser::new(rules, "grammar".into()).expect("The bootstrap parser should be valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GrammarError;
+
+    fn read_meta_grammar(parser: &Parser) -> Parser {
+        let out = parser
+            .parse(META_GRAMMAR)
+            .expect("The meta grammar should parse");
+        crate::grammar_into_parser(META_GRAMMAR, out).expect("The meta grammar should be valid")
+    }
+
+    // The meta grammar read by the bootstrap parser, and then by the parser
+    // that gave, should be the same grammar both times.
+    #[test]
+    fn meta_grammar_matches_bootstrap_parser() {
+        let mut bootstrapped = read_meta_grammar(&Parser::bootstrap_parser());
+        bootstrapped.memoize = true;
+        let reparsed = read_meta_grammar(&bootstrapped);
+
+        assert_eq!(reparsed.start, bootstrapped.start);
+        assert_eq!(reparsed.rules, bootstrapped.rules);
+        assert_eq!(reparsed.display_names, bootstrapped.display_names);
+        assert_eq!(reparsed.inline, bootstrapped.inline);
+        assert_eq!(reparsed.comments, bootstrapped.comments);
+        assert_eq!(bootstrapped.comments.len(), 4);
+    }
+
+    #[test]
+    fn grammar_parser_is_built_once() {
+        assert!(std::ptr::eq(
+            Parser::grammar_parser(),
+            Parser::grammar_parser()
+        ));
+    }
+
+    fn syntax_error(grammar: &str) -> String {
+        match &Parser::from_grammar(grammar).err().unwrap()[..] {
+            [GrammarError::Syntax { msg, .. }] => msg.clone(),
+            errors => panic!("Expected one syntax error, got {errors:?}"),
+        }
+    }
+
+    #[test]
+    fn syntax_errors_expect_parts_of_grammars_by_name() {
+        assert_eq!(syntax_error(""), "Expected one of rule name, `-` or `@`.");
+        assert_eq!(syntax_error("s = ;"), "Expected expression here.");
+        assert_eq!(syntax_error("s = [^] ;"), "Expected character here.");
+        assert_eq!(syntax_error(r#"s = ("a"){x} ;"#), "Expected number here.");
+        assert_eq!(
+            syntax_error(r#"s = re"a ;"#),
+            r#"Expected one of character, escape sequence or `"`."#
+        );
+    }
+
+    #[test]
+    fn syntax_errors_dont_expect_comments() {
+        let errors = Parser::from_grammar("s = a b").err().unwrap();
+        let msg = errors[0].to_string();
+
+        assert!(msg.contains("`;`"), "{msg}");
+        assert!(
+            !msg.contains("comment") && !msg.contains("COMMENT"),
+            "{msg}"
+        );
+    }
+}
@@ -1,205 +1,491 @@
+mod analysis;
+mod ast;
+mod char_class;
+mod cst;
 mod error;
+mod memo;
+mod meta;
 mod parse_expr;
-use std::collections::HashMap;
+mod shared_str;
+mod span;
+mod validate;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
+pub use ast::*;
+pub use char_class::*;
+pub use cst::*;
 pub use error::*;
+pub use memo::*;
+pub use meta::*;
 pub use parse_expr::*;
+pub use shared_str::*;
+pub use span::*;
 
-pub struct Parser<'a> {
-    pub rules: HashMap<&'a str, (ParseExpr<'a>, bool)>,
-    pub start: &'a str,
+// Rules with these names are skipped between tokens, wherever the grammar
+// doesn't use `_`. A grammar with neither skips whitespace.
+pub const WHITESPACE: &str = "WHITESPACE";
+pub const COMMENT: &str = "COMMENT";
+
+pub struct Parser {
+    // Private, as the regexes, left recursive rules and trivia below are all
+    // worked out from the rules when the parser is built.
+    rules: HashMap<SharedStr, (ParseExpr, bool)>,
+    pub start: SharedStr,
+    pub memoize: bool,
+    // Problems with the grammar that don't stop it from being used, such as
+    // rules that can't be reached from the start rule.
+    pub warnings: Vec<GrammarError>,
+    pub consume_all: bool,
+    pub context_lines: usize,
+    pub display_names: HashMap<SharedStr, SharedStr>,
+    // Rules marked with `-`, whose children go straight into their parent's
+    // node in the abstract tree.
+    pub inline: HashSet<SharedStr>,
+    // The grammar's comments, in the order they were written, so that tools
+    // that rewrite the grammar can put them back where they were.
+    pub comments: Vec<SharedStr>,
+    grammar: Option<Arc<str>>,
+    definitions: Vec<SharedStr>,
+    // Earlier definitions of rules that are defined more than once. They're
+    // never used, but are still checked like any other.
+    replaced: Vec<(SharedStr, ParseExpr)>,
+    regexes: HashMap<SharedStr, Regex>,
+    left_recursive: HashSet<SharedStr>,
+    trivia: Vec<AtomicExpr>,
 }
 
-impl<'a> Parser<'a> {
-    pub fn parse(&self, source: &'a str) -> Result<parse_expr::ParseOut<'a>, ParseError<String>> {
-        match self.rules.get(&self.start) {
-            Some((rule, group)) => {
-                let mut idx = 0;
-                match rule
-                    .parse(&mut vec![self.start], group, self, source, &mut idx)
-                    .into_result()
-                {
-                    Ok(p) => Ok(p),
-                    Err(e) => Err(e.into()),
-                }
+impl Parser {
+    pub fn new(
+        rules: HashMap<SharedStr, (ParseExpr, bool)>,
+        start: SharedStr,
+    ) -> Result<Self, GrammarErrors> {
+        let mut definitions: Vec<SharedStr> = rules.keys().cloned().collect();
+        definitions.sort();
+        Self::build(None, definitions, rules, vec![], start)
+    }
+
+    // Builds a parser from rules in the order they appear in `grammar`. The
+    // first rule is the start rule, and later duplicates replace earlier ones.
+    pub fn from_definitions(
+        grammar: Arc<str>,
+        definitions: Vec<(SharedStr, ParseExpr, bool)>,
+    ) -> Result<Self, GrammarErrors> {
+        let start = definitions
+            .first()
+            .map_or_else(|| SharedStr::from(""), |(id, _, _)| id.clone());
+        let names = definitions.iter().map(|(id, _, _)| id.clone()).collect();
+        let mut rules = HashMap::new();
+        let mut replaced = vec![];
+        for (id, rule, group) in definitions {
+            if let Some((rule, _)) = rules.insert(id.clone(), (rule, group)) {
+                replaced.push((id, rule));
             }
-            _ => todo!(),
         }
+        Self::build(Some(grammar), names, rules, replaced, start)
     }
 
-    pub fn grammar_parser() -> Self {
-        let grammar = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::OneOrMore {
-                    e: Box::new(ParseExpr::Atomic(AtomicExpr::NonTerminal("rule"))),
-                },
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                ParseExpr::Atomic(AtomicExpr::EndOfFile),
-            ],
-        };
+    fn build(
+        grammar: Option<Arc<str>>,
+        definitions: Vec<SharedStr>,
+        rules: HashMap<SharedStr, (ParseExpr, bool)>,
+        replaced: Vec<(SharedStr, ParseExpr)>,
+        start: SharedStr,
+    ) -> Result<Self, GrammarErrors> {
+        let mut patterns = vec![];
+        let bodies = rules.values().map(|(rule, _)| rule);
+        for rule in bodies.chain(replaced.iter().map(|(_, rule)| rule)) {
+            rule.visit_atomics(&mut |atomic| {
+                if let AtomicExpr::Regex(re_str) = atomic {
+                    patterns.push(re_str.clone());
+                }
+            });
+        }
 
-        let rule = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                ParseExpr::Optional {
-                    e: Box::new(ParseExpr::Atomic(AtomicExpr::Terminal("@"))),
-                },
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("non_terminal")),
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                ParseExpr::Atomic(AtomicExpr::Terminal("=")),
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("sequence")),
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                ParseExpr::Atomic(AtomicExpr::Terminal(";")),
-                ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-            ],
-        };
+        // Invalid patterns are left out here and reported by `validate`.
+        let mut regexes = HashMap::new();
+        for re_str in patterns {
+            if let Ok(re) = compile_regex(&re_str) {
+                regexes.insert(re_str, re);
+            }
+        }
 
-        let sequence = ParseExpr::Choice {
-            es: vec![
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("modifier")),
-                        ParseExpr::Atomic(AtomicExpr::Regex("\\s+")),
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("sequence")),
-                    ],
-                },
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("modifier")),
-                        ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                        ParseExpr::Atomic(AtomicExpr::Terminal("|")),
-                        ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("sequence")),
-                    ],
-                },
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("modifier")),
-            ],
-        };
+        let trivia = [WHITESPACE, COMMENT]
+            .into_iter()
+            .filter_map(|name| rules.get_key_value(name))
+            .map(|(id, _)| AtomicExpr::NonTerminal(id.clone()))
+            .collect();
 
-        let modifier = ParseExpr::Choice {
-            es: vec![
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::Terminal("_")),
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("primary")),
-                    ],
-                },
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("primary")),
-                        ParseExpr::Atomic(AtomicExpr::Terminal("+")),
-                    ],
-                },
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("primary")),
-                        ParseExpr::Atomic(AtomicExpr::Terminal("*")),
-                    ],
-                },
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("primary")),
-                        ParseExpr::Atomic(AtomicExpr::Terminal("?")),
-                    ],
-                },
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("primary")),
-            ],
+        let mut parser = Parser {
+            rules,
+            start,
+            memoize: false,
+            warnings: vec![],
+            consume_all: false,
+            context_lines: 0,
+            display_names: HashMap::new(),
+            inline: HashSet::new(),
+            comments: vec![],
+            grammar,
+            definitions,
+            replaced,
+            regexes,
+            left_recursive: HashSet::new(),
+            trivia,
         };
 
-        let primary = ParseExpr::Choice {
-            es: vec![
-                ParseExpr::Sequence {
-                    es: vec![
-                        ParseExpr::Atomic(AtomicExpr::Terminal("(")),
-                        ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                        ParseExpr::Atomic(AtomicExpr::NonTerminal("sequence")),
-                        ParseExpr::Atomic(AtomicExpr::Regex("\\s*")),
-                        ParseExpr::Atomic(AtomicExpr::Terminal(")")),
-                    ],
-                },
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("atomic")),
-            ],
-        };
+        let (warnings, errors): (Vec<_>, Vec<_>) = parser
+            .validate()
+            .into_iter()
+            .partition(GrammarError::is_warning);
+        if !errors.is_empty() {
+            return Err(GrammarErrors(errors));
+        }
+        parser.warnings = warnings;
+        parser.left_recursive = parser.left_recursive_rules();
 
-        let atomic = ParseExpr::Choice {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("terminal")),
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("regex")),
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("non_terminal")),
-            ],
-        };
+        Ok(parser)
+    }
 
-        let regex = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::Terminal("re")),
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("STRING")),
-            ],
-        };
+    pub fn rules(&self) -> &HashMap<SharedStr, (ParseExpr, bool)> {
+        &self.rules
+    }
 
-        let non_terminal = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::NonTerminal("ALPHA")),
-                ParseExpr::ZeroOrMore {
-                    e: Box::new(ParseExpr::Choice {
-                        es: vec![
-                            ParseExpr::Atomic(AtomicExpr::NonTerminal("ALPHA")),
-                            ParseExpr::Atomic(AtomicExpr::NonTerminal("DIGIT")),
-                        ],
-                    }),
-                },
-            ],
-        };
+    pub fn parse<'a>(&self, source: &'a str) -> Result<ParseOut<'a>, ParseError<String>> {
+        self.run(source, &mut Memo::default(), self.consume_all)
+    }
 
-        let terminal = ParseExpr::Atomic(AtomicExpr::NonTerminal("STRING"));
-
-        let string = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::Terminal("\"")),
-                ParseExpr::ZeroOrMore {
-                    e: Box::new(ParseExpr::Choice {
-                        es: vec![
-                            ParseExpr::Atomic(AtomicExpr::NonTerminal("escape")),
-                            ParseExpr::Atomic(AtomicExpr::NonTerminal("char")),
-                        ],
-                    }),
-                },
-                ParseExpr::Atomic(AtomicExpr::Terminal("\"")),
-            ],
+    // Parses `source`, skipping ahead to the synchronization point of the
+    // innermost `e ~ sync` whenever `e` fails part way through. Each skipped
+    // stretch becomes an `Error` node in the tree, and its error is returned
+    // alongside the tree. A failure that can't be recovered from ends the
+    // tree with one more `Error` node, for the rest of the input. The tree is
+    // `None` if nothing matched before that failure, which is then the only
+    // error.
+    pub fn parse_recovering<'a>(
+        &self,
+        source: &'a str,
+    ) -> (Option<ParseOut<'a>>, Vec<ParseError<String>>) {
+        let mut memo = Memo::recovering();
+        let out = match self.run(source, &mut memo, self.consume_all) {
+            Ok(out) => out,
+            Err(e) => match memo.take_partial() {
+                Some(partial) => self.fail_partial(source, partial, e),
+                None => return (None, vec![e]),
+            },
         };
 
-        let escape = ParseExpr::Sequence {
-            es: vec![
-                ParseExpr::Atomic(AtomicExpr::Terminal("\\")),
-                ParseExpr::Atomic(AtomicExpr::Regex("\\S")),
-            ],
+        let errors = out.errors().into_iter().cloned().collect();
+        (Some(out), errors)
+    }
+
+    // Follows what was matched before a failure with the rest of `source`,
+    // as skipped over by that failure.
+    fn fail_partial<'a>(
+        &self,
+        source: &'a str,
+        partial: ParseOut<'a>,
+        error: ParseError<String>,
+    ) -> ParseOut<'a> {
+        let rest = Span::new(partial.span.end, source.len());
+        let skipped = ParseOut {
+            rule: self.start.clone(),
+            out: ParseGrouping::Error {
+                skipped: rest.text(source),
+                error: Arc::new(error),
+            },
+            span: rest,
+            trivia: vec![],
+            call: false,
+            literal: false,
         };
 
-        let char = ParseExpr::Atomic(AtomicExpr::Regex("[^\\|\\\\\"]"));
-
-        let alpha = ParseExpr::Atomic(AtomicExpr::Regex("[a-zA-Z_]"));
-        let digit = ParseExpr::Atomic(AtomicExpr::Regex("[0-9]"));
-
-        let parser = Parser {
-            rules: HashMap::from([
-                ("grammar", (grammar, false)),
-                ("rule", (rule, false)),
-                ("sequence", (sequence, false)),
-                ("modifier", (modifier, false)),
-                ("primary", (primary, false)),
-                ("atomic", (atomic, false)),
-                ("terminal", (terminal, false)),
-                ("non_terminal", (non_terminal, true)),
-                ("regex", (regex, false)),
-                ("STRING", (string, true)),
-                ("escape", (escape, false)),
-                ("char", (char, false)),
-                ("ALPHA", (alpha, false)),
-                ("DIGIT", (digit, false)),
-            ]),
-            start: "grammar",
+        ParseOut {
+            rule: self.start.clone(),
+            span: Span::new(partial.span.start, rest.end),
+            out: ParseGrouping::Sequence {
+                ts: Arc::from([partial, skipped]),
+            },
+            trivia: vec![],
+            call: false,
+            literal: false,
+        }
+    }
+
+    // The start rule is entered like any other call to it, so that it is
+    // memoized and grows its seed if it's left recursive.
+    fn run<'a>(
+        &self,
+        source: &'a str,
+        memo: &mut Memo<'a>,
+        consume_all: bool,
+    ) -> Result<ParseOut<'a>, ParseError<String>> {
+        let mut idx = 0;
+        let start = AtomicExpr::NonTerminal(self.start.clone());
+        let mut result = start.parse(&mut vec![], self, source, &mut idx, memo);
+        if consume_all {
+            result = self.expect_end(source, idx, memo, result);
+        }
+
+        result.into_result().map_err(|e| {
+            let mut e = ParseError::from(e);
+            e.locate(source, self.context_lines);
+            e
+        })
+    }
+
+    // Skips whitespace and comments at `idx`, as matched by the `WHITESPACE`
+    // and `COMMENT` rules, and returns what was skipped. Trivia is optional,
+    // so failing to match it is never reported.
+    fn skip_trivia<'a>(
+        &self,
+        rules: &mut Vec<SharedStr>,
+        source: &'a str,
+        idx: &mut usize,
+        memo: &mut Memo<'a>,
+    ) -> Vec<ParseOut<'a>> {
+        if memo.is_skipping_trivia() {
+            return vec![];
+        }
+
+        if self.trivia.is_empty() {
+            let end = source.len() - source[*idx..].trim_start().len();
+            if end == *idx {
+                return vec![];
+            }
+            let span = Span::new(*idx, end);
+            *idx = end;
+            return vec![ParseOut {
+                rule: SharedStr::from(WHITESPACE),
+                out: ParseGrouping::Terminal(span.text(source)),
+                span,
+                trivia: vec![],
+                call: true,
+                literal: false,
+            }];
+        }
+
+        memo.set_skipping_trivia(true);
+        let mut trivia = vec![];
+        'skip: loop {
+            for atomic in &self.trivia {
+                let before = *idx;
+                if let Ok(out) = atomic.parse(rules, self, source, idx, memo).into_result() {
+                    if *idx > before {
+                        trivia.push(out);
+                        continue 'skip;
+                    }
+                }
+                *idx = before;
+            }
+            break;
+        }
+        memo.set_skipping_trivia(false);
+
+        trivia
+    }
+
+    // Fails a parse that stopped before the end of `source`, other than
+    // trailing trivia. Whatever could have carried on from there is expected
+    // too.
+    fn expect_end<'a>(
+        &self,
+        source: &'a str,
+        idx: usize,
+        memo: &mut Memo<'a>,
+        result: Fallible<ParseOut<'a>, ParseError<SharedStr>>,
+    ) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+        // Failures are passed on before skipping trivia, which would forget
+        // what they matched.
+        let (out, mut errors) = match result {
+            Fallible::Ok(out) => (out, vec![]),
+            Fallible::Recovered(out, e) => (out, vec![e]),
+            Fallible::Err(e) => return Fallible::Err(e),
         };
 
-        parser
+        let mut end = idx;
+        self.skip_trivia(&mut vec![self.start.clone()], source, &mut end, memo);
+        if end == source.len() {
+            return match errors.pop() {
+                Some(e) => Fallible::Recovered(out, e),
+                None => Fallible::Ok(out),
+            };
+        }
+
+        // The start rule matched, but not everything.
+        memo.set_partial(Some(out));
+        errors.push(ParseError::new_expected_end(&end, vec![self.start.clone()]));
+
+        Fallible::Err(propagate!(ParseError::collect_furthest(errors)).unwrap())
+    }
+}
+
+// Patterns are anchored so matching never scans ahead of `idx`. They are
+// compiled on their own first, so that errors refer to what was written.
+fn compile_regex(re_str: &str) -> Result<Regex, regex::Error> {
+    Regex::new(re_str)?;
+    Regex::new(&format!("^(?:{re_str})"))
+}
+
+// Builds a parser from a grammar a test knows to be valid.
+#[cfg(test)]
+pub(crate) fn test_parser(grammar: &str) -> Parser {
+    let out = Parser::grammar_parser()
+        .parse(grammar)
+        .expect("Test grammars should be valid");
+    crate::grammar_into_parser(grammar, out).expect("Test grammars should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trivia_texts<'a>(out: &ParseOut<'a>, source: &'a str, texts: &mut Vec<&'a str>) {
+        texts.extend(out.trivia.iter().map(|t| t.span.text(source)));
+        match &out.out {
+            ParseGrouping::Sequence { ts } => {
+                ts.iter().for_each(|t| trivia_texts(t, source, texts))
+            }
+            ParseGrouping::Out(out) => trivia_texts(out, source, texts),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn trivia_rules_replace_the_default_whitespace() {
+        let mut parser =
+            test_parser(r##"s = ("x")+ ; WHITESPACE = _" " ; COMMENT = _"#" (_[a-z])* ;"##);
+        parser.consume_all = true;
+        let source = "x #note x";
+
+        assert!(parser.parse("x\nx").is_err());
+        let out = parser.parse(source).unwrap();
+        let mut texts = vec![];
+        trivia_texts(&out, source, &mut texts);
+        assert_eq!(texts, [" ", "#note", " "]);
+    }
+
+    #[test]
+    fn memoized_results_inside_trivia_stay_there() {
+        let grammar =
+            r#"s = "//" _W EOF ; COMMENT = _"//" W ; WHITESPACE = _" " ; W = re"[a-z]+" ;"#;
+        let mut parser = test_parser(grammar);
+
+        assert!(parser.parse("// abc").is_ok());
+        parser.memoize = true;
+        assert!(parser.parse("// abc").is_ok());
+    }
+
+    #[test]
+    fn invalid_regexes_are_grammar_errors() {
+        let grammar = r#"s = re"(a" ;"#;
+        let out = Parser::grammar_parser().parse(grammar).unwrap();
+        let errors = crate::grammar_into_parser(grammar, out).err().unwrap();
+
+        assert!(matches!(
+            &errors[..],
+            [GrammarError::InvalidRegex { pattern, .. }] if pattern == "(a"
+        ));
+    }
+
+    #[test]
+    fn regexes_only_match_where_they_start() {
+        let parser = test_parser(r#"s = _re"b" ;"#);
+
+        assert!(parser.parse("ab").is_err());
+        assert!(parser.parse("ba").is_ok());
+    }
+
+    const STATEMENTS: &str = r#"
+        s     = (stmt ~ ";")* EOF ;
+        stmt  = "let" ident "=" num ";" ;
+        ident = re"[a-z]+" ;
+        num   = re"[0-9]+" ;
+    "#;
+
+    fn skipped<'a>(group: &ParseGrouping<'a>, texts: &mut Vec<&'a str>) {
+        match group {
+            ParseGrouping::Error { skipped, .. } => texts.push(skipped),
+            ParseGrouping::Sequence { ts } => ts.iter().for_each(|t| skipped(&t.out, texts)),
+            ParseGrouping::Optional(Some(group)) => skipped(group, texts),
+            ParseGrouping::Out(out) => skipped(&out.out, texts),
+            ParseGrouping::Terminal(_) | ParseGrouping::Optional(None) => (),
+        }
+    }
+
+    #[test]
+    fn recovery_resumes_after_the_sync_point() {
+        let parser = test_parser(STATEMENTS);
+        let source = "let a = 1; let = x; let c = ;";
+
+        assert!(parser.parse(source).is_err());
+        let (out, errors) = parser.parse_recovering(source);
+        let locations: Vec<_> = errors
+            .iter()
+            .map(|e| e.location().map(|l| (l.line, l.col)))
+            .collect();
+        assert_eq!(locations, [Some((1, 16)), Some((1, 29))]);
+
+        let mut texts = vec![];
+        skipped(&out.unwrap().out, &mut texts);
+        assert_eq!(texts, [" let = x;", " let c = ;"]);
+    }
+
+    #[test]
+    fn recovery_changes_nothing_without_errors() {
+        let parser = test_parser(STATEMENTS);
+        let source = "let a = 1; let b = 2;";
+
+        let (out, errors) = parser.parse_recovering(source);
+        assert!(errors.is_empty());
+        assert_eq!(out.unwrap().span, parser.parse(source).unwrap().span);
+    }
+
+    #[test]
+    fn failures_outside_a_recovery_point_are_final() {
+        let (out, errors) = test_parser(STATEMENTS).parse_recovering("x");
+
+        assert!(out.is_none());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn final_failures_keep_what_was_recovered_before_them() {
+        let parser = test_parser(STATEMENTS);
+        let source = "let = x; let a = 1; 123";
+
+        let (out, errors) = parser.parse_recovering(source);
+        let messages: Vec<_> = errors.iter().map(|e| e.message().unwrap()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Expected `[a-z]+` here.",
+                "Expected one of end of input or `let`."
+            ]
+        );
+
+        // The statement after the recovered one is kept too.
+        let out = out.unwrap();
+        assert_eq!(out.span, Span::new(0, source.len()));
+        assert!(matches!(
+            &out.out,
+            ParseGrouping::Sequence { ts } if ts[0].span == Span::new(0, 19)
+        ));
+        let mut texts = vec![];
+        skipped(&out.out, &mut texts);
+        assert_eq!(texts, ["let = x;", " 123"]);
+    }
+
+    #[test]
+    fn consume_all_requires_the_whole_input() {
+        let mut parser = test_parser(r#"s = ("a")+ ;"#);
+
+        assert_eq!(parser.parse("a a b").unwrap().span, Span::new(0, 3));
+        parser.consume_all = true;
+        assert!(parser.parse("a a ").is_ok());
+        let e = parser.parse("a a b").unwrap_err();
+        assert_eq!(e.message(), Some("Expected one of end of input or `a`."));
+        assert_eq!(e.offset(), Some(4));
     }
 }
@@ -1,214 +1,691 @@
-use regex::Regex;
+use std::sync::Arc;
 
-use super::{Fallible, ParseError};
+use super::{propagate, CharClass, Fallible, Memo, ParseError, SharedStr, Span};
 
-#[derive(Debug)]
-pub enum AtomicExpr<'a> {
-    Terminal(&'a str),
-    Regex(&'a str),
-    NonTerminal(&'a str),
+#[derive(Debug, PartialEq, Eq)]
+pub enum AtomicExpr {
+    Terminal(SharedStr),
+    Regex(SharedStr),
+    Class(CharClass),
+    NonTerminal(SharedStr),
     EndOfFile,
 }
 
-impl<'a> AtomicExpr<'a> {
-    pub fn parse(
+impl AtomicExpr {
+    pub fn parse<'a>(
         &self,
-        rules: &mut Vec<&'a str>,
-        parser: &super::Parser<'a>,
+        rules: &mut Vec<SharedStr>,
+        parser: &super::Parser,
         source: &'a str,
         idx: &mut usize,
-    ) -> Fallible<ParseOut<'a>, ParseError<&'a str>> {
+        memo: &mut Memo<'a>,
+    ) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+        memo.set_partial(None);
         match self {
             AtomicExpr::NonTerminal(non_term) => match parser.rules.get(non_term) {
                 Some((expr, group)) => {
-                    rules.push(non_term);
-                    let result = expr.parse(rules, group, parser, source, idx);
+                    let left_recursive = parser.left_recursive.contains(non_term);
+                    if parser.memoize || left_recursive {
+                        if let Some(result) = memo.get(non_term, rules, idx) {
+                            return result;
+                        }
+                    }
+
+                    let start = *idx;
+                    if left_recursive {
+                        let result =
+                            grow_seed(non_term, expr, rules, group, parser, source, idx, memo);
+                        return name_failure(non_term, rules, parser, source, start, result);
+                    }
+
+                    rules.push(non_term.clone());
+                    let result = expr.parse(rules, group, parser, source, idx, memo);
                     rules.pop();
+                    let result = call_result(non_term, result, memo);
+                    let result = name_failure(non_term, rules, parser, source, start, result);
+
+                    if parser.memoize && !memo.is_growing(start) {
+                        memo.insert(non_term, rules, start, *idx, &result);
+                    }
                     result
                 }
-                _ => return Fallible::Err(ParseError::UnknownNonTerminal(non_term)),
+                _ => Fallible::Err(ParseError::UnknownNonTerminal(non_term.clone())),
             },
             AtomicExpr::Terminal(term) => {
-                if source[*idx..].starts_with(term) {
-                    let s = &source[*idx..*idx + term.len()];
-                    *idx += term.len();
+                if source[*idx..].starts_with(term.as_str()) {
+                    let span = Span::new(*idx, *idx + term.len());
+                    *idx = span.end;
                     Fallible::Ok(ParseOut {
-                        rule: rules.last().unwrap(),
-                        out: ParseGrouping::Terminal(s),
+                        rule: rules.last().unwrap().clone(),
+                        out: ParseGrouping::Terminal(span.text(source)),
+                        span,
+                        trivia: vec![],
+                        call: false,
+                        literal: true,
                     })
                 } else {
                     Fallible::Err(ParseError::new_bad_match(
-                        source,
                         idx,
                         format!("Expected `{term}` here."),
-                        vec![term],
+                        vec![term.clone()],
                         rules.clone(),
                     ))
                 }
             }
             AtomicExpr::Regex(re_str) => {
-                let re = Regex::new(re_str).unwrap();
+                let re = parser
+                    .regexes
+                    .get(re_str)
+                    .expect("Regexes should be compiled when the parser is built");
 
                 match re.find(&source[*idx..]) {
                     Some(m) => {
-                        if m.start() != 0 {
-                            return Fallible::Err(ParseError::new_bad_match(
-                                source,
-                                idx,
-                                format!("Failed to match `{re_str}`."),
-                                vec![re_str],
-                                rules.clone(),
-                            ));
-                        }
-                        let s = &source[*idx..*idx + m.end()];
-                        *idx += m.end();
+                        let span = Span::new(*idx, *idx + m.end());
+                        *idx = span.end;
                         Fallible::Ok(ParseOut {
-                            rule: rules.last().unwrap(),
-                            out: ParseGrouping::Terminal(s),
+                            rule: rules.last().unwrap().clone(),
+                            out: ParseGrouping::Terminal(span.text(source)),
+                            span,
+                            trivia: vec![],
+                            call: false,
+                            literal: false,
                         })
                     }
-                    None => {
-                        return Fallible::Err(ParseError::new_bad_match(
-                            source,
-                            idx,
-                            format!("Failed to match `{re_str}`."),
-                            vec![re_str],
-                            rules.clone(),
-                        ));
-                    }
+                    None if is_trivia(re_str) => Fallible::Err(ParseError::new_bad_match(
+                        idx,
+                        "Expected whitespace here.",
+                        vec![],
+                        rules.clone(),
+                    )),
+                    None => Fallible::Err(ParseError::new_bad_match(
+                        idx,
+                        format!("Failed to match `{re_str}`."),
+                        vec![re_str.clone()],
+                        rules.clone(),
+                    )),
                 }
             }
+            AtomicExpr::Class(class) => match source[*idx..].chars().next() {
+                Some(c) if class.matches(c) => {
+                    let span = Span::new(*idx, *idx + c.len_utf8());
+                    *idx = span.end;
+                    Fallible::Ok(ParseOut {
+                        rule: rules.last().unwrap().clone(),
+                        out: ParseGrouping::Terminal(span.text(source)),
+                        span,
+                        trivia: vec![],
+                        call: false,
+                        literal: false,
+                    })
+                }
+                _ => Fallible::Err(ParseError::new_bad_match(
+                    idx,
+                    format!("Expected `{class}` here."),
+                    vec![class.text.clone()],
+                    rules.clone(),
+                )),
+            },
             AtomicExpr::EndOfFile => {
-                if *idx + 1 >= source.len() {
+                if *idx == source.len() {
                     Fallible::Ok(ParseOut {
-                        rule: rules.last().unwrap(),
-                        out: ParseGrouping::Terminal("EOF"),
+                        rule: rules.last().unwrap().clone(),
+                        out: ParseGrouping::Terminal(&source[*idx..*idx]),
+                        span: Span::new(*idx, *idx),
+                        trivia: vec![],
+                        call: false,
+                        literal: false,
                     })
                 } else {
-                    todo!();
+                    Fallible::Err(ParseError::new_expected_end(idx, rules.clone()))
                 }
             }
         }
     }
 }
 
-#[derive(Debug)]
-pub enum ParseExpr<'a> {
-    Atomic(AtomicExpr<'a>),
-    Sequence { es: Vec<ParseExpr<'a>> },
-    Choice { es: Vec<ParseExpr<'a>> },
-    ZeroOrMore { e: Box<ParseExpr<'a>> },
-    OneOrMore { e: Box<ParseExpr<'a>> },
-    Optional { e: Box<ParseExpr<'a>> },
+// Marks what a rule matched as one call to it, as is what it matched before
+// failing. A body that only calls another rule gives that rule's call, which
+// is wrapped to keep both.
+fn call_result<'a>(
+    non_term: &SharedStr,
+    result: Fallible<ParseOut<'a>, ParseError<SharedStr>>,
+    memo: &mut Memo<'a>,
+) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+    let call = |out: ParseOut<'a>| {
+        if out.rule == *non_term && !out.call {
+            return ParseOut { call: true, ..out };
+        }
+        ParseOut {
+            rule: non_term.clone(),
+            span: out.span,
+            out: ParseGrouping::Out(Arc::new(out)),
+            trivia: vec![],
+            call: true,
+            literal: false,
+        }
+    };
+
+    match result {
+        Fallible::Ok(out) => Fallible::Ok(call(out)),
+        Fallible::Recovered(out, e) => Fallible::Recovered(call(out), e),
+        Fallible::Err(e) => {
+            let partial = memo.take_partial().map(call);
+            memo.set_partial(partial);
+            Fallible::Err(e)
+        }
+    }
+}
+
+// Whitespace is never what the user meant to write next, so patterns that
+// only match whitespace are left out of the expected terminals.
+pub fn is_trivia(re_str: &str) -> bool {
+    matches!(re_str, "\\s*" | "\\s+")
+}
+
+// A rule with a display name that fails before matching anything other than
+// whitespace is reported as a whole, by that name.
+fn name_failure<'a>(
+    non_term: &SharedStr,
+    rules: &[SharedStr],
+    parser: &super::Parser,
+    source: &'a str,
+    start: usize,
+    result: Fallible<ParseOut<'a>, ParseError<SharedStr>>,
+) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+    match (result, parser.display_names.get(non_term)) {
+        (Fallible::Err(mut e), Some(name))
+            if e.offset()
+                .is_some_and(|at| source[start..at].trim().is_empty()) =>
+        {
+            e.expect_rule(name, rules);
+            Fallible::Err(e)
+        }
+        (result, _) => result,
+    }
+}
+
+// Parses a left recursive rule by first failing every recursive call at
+// `idx`, then re-parsing with the previous result as the seed for as long as
+// each attempt consumes more input than the last.
+#[allow(clippy::too_many_arguments)]
+fn grow_seed<'a>(
+    non_term: &SharedStr,
+    expr: &ParseExpr,
+    rules: &mut Vec<SharedStr>,
+    group: &bool,
+    parser: &super::Parser,
+    source: &'a str,
+    idx: &mut usize,
+    memo: &mut Memo<'a>,
+) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+    let start = *idx;
+    let mut best = Fallible::Err(ParseError::new_bad_match(
+        idx,
+        format!("Expected `{non_term}` here."),
+        vec![non_term.clone()],
+        rules.clone(),
+    ));
+    let mut end = start;
+    memo.insert(non_term, rules, start, end, &best);
+    memo.begin_growing(start);
+
+    loop {
+        *idx = start;
+        rules.push(non_term.clone());
+        let result = expr.parse(rules, group, parser, source, idx, memo);
+        let result = call_result(non_term, result, memo);
+        rules.pop();
+
+        match result {
+            Fallible::Err(e) if matches!(best, Fallible::Err(_)) => {
+                best = Fallible::Err(e);
+                break;
+            }
+            Fallible::Err(_) => break,
+            _ if *idx <= end && !matches!(best, Fallible::Err(_)) => break,
+            result => {
+                end = *idx;
+                memo.insert(non_term, rules, start, end, &result);
+                best = result;
+            }
+        }
+    }
+
+    memo.end_growing();
+    if memo.is_growing(start) {
+        memo.remove(non_term, start);
+    } else {
+        memo.insert(non_term, rules, start, end, &best);
+    }
+
+    *idx = end;
+    best
+}
+
+// Keeps what a failed sequence or repetition matched, up to and including
+// whatever its failing part matched, for `Parser::parse_recovering`.
+fn keep_partial<'a>(
+    rules: &[SharedStr],
+    group: &bool,
+    source: &'a str,
+    start: usize,
+    mut outs: Vec<ParseOut<'a>>,
+    memo: &mut Memo<'a>,
+) {
+    outs.extend(memo.take_partial());
+    let partial = match outs.last() {
+        Some(last) if last.span.end > start => {
+            let span = Span::new(start, last.span.end);
+            Some(sequence_out(rules, group, source, span, outs))
+        }
+        _ => None,
+    };
+    memo.set_partial(partial);
 }
 
-impl<'a> ParseExpr<'a> {
-    pub fn parse(
+// What a sequence or repetition matched, either as a list of what each part
+// matched or, under `@`, as one terminal. Trivia in front of a terminal is
+// kept out of its text and attached to it instead.
+fn sequence_out<'a>(
+    rules: &[SharedStr],
+    group: &bool,
+    source: &'a str,
+    span: Span,
+    mut ts: Vec<ParseOut<'a>>,
+) -> ParseOut<'a> {
+    let rule = rules.last().unwrap().clone();
+    if !*group {
+        return ParseOut {
+            rule,
+            out: ParseGrouping::Sequence { ts: ts.into() },
+            span,
+            trivia: vec![],
+            call: false,
+            literal: false,
+        };
+    }
+
+    let trivia = match ts.first_mut() {
+        Some(first) => std::mem::take(&mut first.trivia),
+        None => vec![],
+    };
+    let span = Span::new(trivia.last().map_or(span.start, |t| t.span.end), span.end);
+    ParseOut {
+        rule,
+        out: ParseGrouping::Terminal(span.text(source)),
+        span,
+        trivia,
+        call: false,
+        literal: false,
+    }
+}
+
+// What `e?` matched, with the trivia in front of it. A call or a literal is
+// kept whole, so that it's still marked as one.
+fn optional_out<'a>(rules: &[SharedStr], mut out: ParseOut<'a>) -> ParseOut<'a> {
+    let trivia = std::mem::take(&mut out.trivia);
+    let span = out.span;
+    let inner = if out.call || out.literal {
+        ParseGrouping::Out(Arc::new(out))
+    } else {
+        out.out
+    };
+
+    ParseOut {
+        rule: rules.last().unwrap().clone(),
+        out: ParseGrouping::Optional(Some(Arc::new(inner))),
+        span,
+        trivia,
+        call: false,
+        literal: false,
+    }
+}
+
+// Matches `e` between `min` and `max` times, with an optional separator
+// between each match and whether one may follow the last. Matching too few
+// times fails where the next match stopped.
+#[allow(clippy::too_many_arguments)]
+fn repeat<'a>(
+    e: &ParseExpr,
+    sep: Option<(&ParseExpr, bool)>,
+    min: usize,
+    max: Option<usize>,
+    rules: &mut Vec<SharedStr>,
+    group: &bool,
+    parser: &super::Parser,
+    source: &'a str,
+    idx: &mut usize,
+    memo: &mut Memo<'a>,
+) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
+    let start = *idx;
+    let mut outs = vec![];
+    let mut errors = vec![];
+    while max.map_or(true, |max| outs.len() < max) {
+        let before = *idx;
+        let mut trailing = false;
+        if let (Some((sep, allow_trailing)), false) = (sep, outs.is_empty()) {
+            match sep.parse(rules, group, parser, source, idx, memo) {
+                Fallible::Ok(_) => (),
+                Fallible::Recovered(_, e) => errors.push(e),
+                Fallible::Err(e) => {
+                    errors.push(e);
+                    break;
+                }
+            }
+            trailing = allow_trailing;
+        }
+
+        match e.parse(rules, group, parser, source, idx, memo) {
+            Fallible::Ok(out) => outs.push(out),
+            Fallible::Recovered(out, e) => {
+                outs.push(out);
+                errors.push(e);
+            }
+            Fallible::Err(e) => {
+                errors.push(e);
+                if !trailing {
+                    *idx = before;
+                }
+                break;
+            }
+        }
+
+        // An iteration that consumes nothing would match the same way every
+        // time, so there's no point going on once there are enough.
+        if *idx == before && outs.len() >= min {
+            break;
+        }
+    }
+
+    if outs.len() < min {
+        *idx = start;
+        let mut e = propagate!(ParseError::collect_furthest(errors)).unwrap();
+        e.capture(rules, &outs);
+        keep_partial(rules, group, source, start, outs, memo);
+        return Fallible::Err(e);
+    }
+
+    let err = propagate!(ParseError::collect_furthest(errors));
+    let span = Span::new(start, *idx);
+    let out = sequence_out(rules, group, source, span, outs);
+
+    match err {
+        Some(e) => Fallible::Recovered(out, e),
+        None => Fallible::Ok(out),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseExpr {
+    Atomic(AtomicExpr),
+    Sequence {
+        es: Vec<ParseExpr>,
+    },
+    Choice {
+        es: Vec<ParseExpr>,
+    },
+    ZeroOrMore {
+        e: Box<ParseExpr>,
+    },
+    OneOrMore {
+        e: Box<ParseExpr>,
+    },
+    Optional {
+        e: Box<ParseExpr>,
+    },
+    // `e{min,max}`, where a missing `max` means there's no limit.
+    Repeat {
+        e: Box<ParseExpr>,
+        min: usize,
+        max: Option<usize>,
+    },
+    // One or more `e`, with `sep` between them and, if `trailing`, after
+    // the last one too. Only the matches of `e` are kept.
+    Separated {
+        e: Box<ParseExpr>,
+        sep: Box<ParseExpr>,
+        trailing: bool,
+    },
+    And {
+        e: Box<ParseExpr>,
+    },
+    Not {
+        e: Box<ParseExpr>,
+    },
+    Recover {
+        e: Box<ParseExpr>,
+        sync: Box<ParseExpr>,
+    },
+    // Skips the parser's trivia before `e`, and attaches it to what `e`
+    // matched. Atomics that aren't marked with `_` are wrapped in this.
+    SkipTrivia {
+        e: Box<ParseExpr>,
+    },
+}
+
+impl ParseExpr {
+    pub fn visit<'e, F: FnMut(&'e ParseExpr)>(&'e self, f: &mut F) {
+        f(self);
+        match self {
+            ParseExpr::Atomic(_) => (),
+            ParseExpr::Sequence { es } | ParseExpr::Choice { es } => {
+                es.iter().for_each(|e| e.visit(f))
+            }
+            ParseExpr::ZeroOrMore { e }
+            | ParseExpr::OneOrMore { e }
+            | ParseExpr::Optional { e }
+            | ParseExpr::Repeat { e, .. }
+            | ParseExpr::And { e }
+            | ParseExpr::Not { e }
+            | ParseExpr::SkipTrivia { e } => e.visit(f),
+            ParseExpr::Separated { e, sep: other, .. } | ParseExpr::Recover { e, sync: other } => {
+                e.visit(f);
+                other.visit(f);
+            }
+        }
+    }
+
+    pub fn visit_atomics<'e, F: FnMut(&'e AtomicExpr)>(&'e self, f: &mut F) {
+        self.visit(&mut |e| {
+            if let ParseExpr::Atomic(atomic) = e {
+                f(atomic)
+            }
+        })
+    }
+
+    pub fn parse<'a>(
         &self,
-        rules: &mut Vec<&'a str>,
+        rules: &mut Vec<SharedStr>,
         group: &bool,
-        parser: &super::Parser<'a>,
+        parser: &super::Parser,
         source: &'a str,
         idx: &mut usize,
-    ) -> Fallible<ParseOut<'a>, ParseError<&'a str>> {
+        memo: &mut Memo<'a>,
+    ) -> Fallible<ParseOut<'a>, ParseError<SharedStr>> {
         let x = match self {
-            ParseExpr::Atomic(atomic) => atomic.parse(rules, parser, source, idx),
+            ParseExpr::Atomic(atomic) => atomic.parse(rules, parser, source, idx, memo),
             ParseExpr::Choice { es } => {
                 let mut errors = vec![];
+                let mut partial: Option<ParseOut<'a>> = None;
                 for e in es {
-                    match e.parse(rules, group, parser, source, idx) {
+                    match e.parse(rules, group, parser, source, idx, memo) {
                         Fallible::Ok(s) => {
                             return Fallible::Ok(ParseOut {
-                                rule: rules.last().unwrap(),
-                                out: ParseGrouping::Out(Box::new(s)),
+                                rule: rules.last().unwrap().clone(),
+                                span: s.span,
+                                out: ParseGrouping::Out(Arc::new(s)),
+                                trivia: vec![],
+                                call: false,
+                                literal: false,
                             })
                         }
                         Fallible::Recovered(s, e) => {
                             errors.push(e);
                             return Fallible::Recovered(
                                 ParseOut {
-                                    rule: rules.last().unwrap(),
-                                    out: ParseGrouping::Out(Box::new(s)),
+                                    rule: rules.last().unwrap().clone(),
+                                    span: s.span,
+                                    out: ParseGrouping::Out(Arc::new(s)),
+                                    trivia: vec![],
+                                    call: false,
+                                    literal: false,
                                 },
-                                ParseError::collect_furthest(errors)?.unwrap(),
+                                propagate!(ParseError::collect_furthest(errors)).unwrap(),
                             );
                         }
-                        Fallible::Err(e) => errors.push(e),
-                    }
-                }
-
-                Fallible::Err(ParseError::collect_furthest(errors)?.unwrap())
-            }
-            ParseExpr::OneOrMore { e } | ParseExpr::ZeroOrMore { e } => {
-                let prev_idx = *idx;
-                let mut outs = if matches!(self, ParseExpr::OneOrMore { .. }) {
-                    vec![e.parse(rules, group, parser, source, idx)?]
-                } else {
-                    vec![]
-                };
-                let mut errors = vec![];
-                loop {
-                    match e.parse(rules, group, parser, source, idx) {
-                        Fallible::Ok(out) => outs.push(out),
-                        Fallible::Recovered(out, e) => {
-                            outs.push(out);
-                            errors.push(e)
-                        }
                         Fallible::Err(e) => {
                             errors.push(e);
-                            break;
+                            // The alternative that got furthest is the one
+                            // that was most likely meant.
+                            if let Some(out) = memo.take_partial() {
+                                partial = Some(match partial {
+                                    Some(p) if p.span.end >= out.span.end => p,
+                                    _ => out,
+                                });
+                            }
                         }
                     }
                 }
 
-                let err = ParseError::collect_furthest(errors)?
-                    .expect("One or More should have at least one error.");
-
-                if *group {
-                    let s = &source[prev_idx..*idx];
-                    Fallible::Recovered(
-                        ParseOut {
-                            rule: rules.last().unwrap(),
-                            out: ParseGrouping::Terminal(s),
-                        },
-                        err,
-                    )
-                } else {
-                    Fallible::Recovered(
-                        ParseOut {
-                            rule: rules.last().unwrap(),
-                            out: ParseGrouping::Sequence { ts: outs },
-                        },
-                        err,
-                    )
+                memo.set_partial(partial);
+                Fallible::Err(propagate!(ParseError::collect_furthest(errors)).unwrap())
+            }
+            ParseExpr::ZeroOrMore { e } => {
+                repeat(e, None, 0, None, rules, group, parser, source, idx, memo)
+            }
+            ParseExpr::OneOrMore { e } => {
+                repeat(e, None, 1, None, rules, group, parser, source, idx, memo)
+            }
+            ParseExpr::Repeat { e, min, max } => {
+                repeat(e, None, *min, *max, rules, group, parser, source, idx, memo)
+            }
+            ParseExpr::Separated { e, sep, trailing } => repeat(
+                e,
+                Some((sep, *trailing)),
+                1,
+                None,
+                rules,
+                group,
+                parser,
+                source,
+                idx,
+                memo,
+            ),
+            ParseExpr::SkipTrivia { e } => {
+                let start = *idx;
+                let trivia = parser.skip_trivia(rules, source, idx, memo);
+                match e.parse(rules, group, parser, source, idx, memo) {
+                    Fallible::Ok(mut out) => {
+                        out.trivia.splice(0..0, trivia);
+                        Fallible::Ok(out)
+                    }
+                    Fallible::Recovered(mut out, e) => {
+                        out.trivia.splice(0..0, trivia);
+                        Fallible::Recovered(out, e)
+                    }
+                    Fallible::Err(e) => {
+                        *idx = start;
+                        Fallible::Err(e)
+                    }
                 }
             }
-            ParseExpr::Optional { e } => match e.parse(rules, group, parser, source, idx) {
-                Fallible::Ok(ParseOut { out, .. }) => Fallible::Ok(ParseOut {
-                    rule: rules.last().unwrap(),
-                    out: ParseGrouping::Optional(Some(Box::new(out))),
-                }),
-                Fallible::Recovered(ParseOut { out, .. }, e) => Fallible::Recovered(
-                    ParseOut {
-                        rule: rules.last().unwrap(),
-                        out: ParseGrouping::Optional(Some(Box::new(out))),
-                    },
-                    e,
-                ),
+            ParseExpr::Optional { e } => match e.parse(rules, group, parser, source, idx, memo) {
+                Fallible::Ok(out) => Fallible::Ok(optional_out(rules, out)),
+                Fallible::Recovered(out, e) => Fallible::Recovered(optional_out(rules, out), e),
                 Fallible::Err(e) => Fallible::Recovered(
                     ParseOut {
-                        rule: rules.last().unwrap(),
+                        rule: rules.last().unwrap().clone(),
                         out: ParseGrouping::Optional(None),
+                        span: Span::new(*idx, *idx),
+                        trivia: vec![],
+                        call: false,
+                        literal: false,
                     },
                     e,
                 ),
             },
+            ParseExpr::And { e: inner } | ParseExpr::Not { e: inner } => {
+                let start = *idx;
+                let result = inner.parse(rules, group, parser, source, idx, memo);
+                let end = *idx;
+                *idx = start;
+
+                // Lookahead matches nothing, even when it fails.
+                memo.set_partial(None);
+                match (self, result) {
+                    (ParseExpr::And { .. }, Fallible::Err(e)) => Fallible::Err(e),
+                    // The error is about what `e` matched, not the trivia in
+                    // front of it.
+                    (ParseExpr::Not { .. }, Fallible::Ok(out) | Fallible::Recovered(out, _)) => {
+                        let at = out.span.start;
+                        Fallible::Err(ParseError::new_unexpected(
+                            source,
+                            &at,
+                            &source[at..end],
+                            rules.clone(),
+                        ))
+                    }
+                    _ => Fallible::Ok(ParseOut {
+                        rule: rules.last().unwrap().clone(),
+                        out: ParseGrouping::Terminal(&source[start..start]),
+                        span: Span::new(start, start),
+                        trivia: vec![],
+                        call: false,
+                        literal: false,
+                    }),
+                }
+            }
+            ParseExpr::Recover { e, sync } => {
+                let start = *idx;
+                let error = match e.parse(rules, group, parser, source, idx, memo) {
+                    Fallible::Err(error) if memo.is_recovering() => error,
+                    result => return result,
+                };
+
+                // Only recover once `e` has matched something, otherwise a
+                // failure here is just the end of whatever surrounds it.
+                let at = match &error {
+                    ParseError::BadMatchError(e) if !source[start..e.idx].trim().is_empty() => {
+                        e.idx
+                    }
+                    _ => return Fallible::Err(error),
+                };
+
+                let partial = memo.take_partial();
+                for (offset, _) in source[at..].char_indices() {
+                    *idx = at + offset;
+                    if sync
+                        .parse(rules, group, parser, source, idx, memo)
+                        .into_result()
+                        .is_ok()
+                    {
+                        let mut error = ParseError::<String>::from(error);
+                        error.locate(source, parser.context_lines);
+                        let span = Span::new(start, *idx);
+                        return Fallible::Ok(ParseOut {
+                            rule: rules.last().unwrap().clone(),
+                            out: ParseGrouping::Error {
+                                skipped: span.text(source),
+                                error: Arc::new(error),
+                            },
+                            span,
+                            trivia: vec![],
+                            call: false,
+                            literal: false,
+                        });
+                    }
+                }
+
+                *idx = start;
+                memo.set_partial(partial);
+                Fallible::Err(error)
+            }
             ParseExpr::Sequence { es } => {
                 let start_idx = *idx;
                 let mut s = vec![];
                 let mut errors = vec![];
                 for e in es {
-                    match e.parse(rules, group, parser, source, idx) {
+                    match e.parse(rules, group, parser, source, idx, memo) {
                         Fallible::Ok(out) => s.push(out),
                         Fallible::Recovered(out, e) => {
                             s.push(out);
@@ -218,23 +695,17 @@ impl<'a> ParseExpr<'a> {
                             *idx = start_idx;
                             errors.push(e);
 
-                            return Fallible::Err(ParseError::collect_furthest(errors)?.unwrap());
+                            let mut e = propagate!(ParseError::collect_furthest(errors)).unwrap();
+                            e.capture(rules, &s);
+                            keep_partial(rules, group, source, start_idx, s, memo);
+                            return Fallible::Err(e);
                         }
                     }
                 }
 
-                let err = ParseError::collect_furthest(errors)?;
-                let out = if *group {
-                    ParseOut {
-                        rule: rules.last().unwrap(),
-                        out: ParseGrouping::Terminal(&source[start_idx..*idx]),
-                    }
-                } else {
-                    ParseOut {
-                        rule: rules.last().unwrap(),
-                        out: ParseGrouping::Sequence { ts: s },
-                    }
-                };
+                let err = propagate!(ParseError::collect_furthest(errors));
+                let span = Span::new(start_idx, *idx);
+                let out = sequence_out(rules, group, source, span, s);
 
                 match err {
                     Some(e) => Fallible::Recovered(out, e),
@@ -247,16 +718,316 @@ impl<'a> ParseExpr<'a> {
     }
 }
 
-#[derive(Debug)]
+// What's under a node is shared, so that a result taken from the memo costs
+// the same however much it matched.
+#[derive(Debug, Clone)]
 pub enum ParseGrouping<'a> {
     Terminal(&'a str),
-    Sequence { ts: Vec<ParseOut<'a>> },
-    Optional(Option<Box<ParseGrouping<'a>>>),
-    Out(Box<ParseOut<'a>>),
+    Sequence {
+        ts: Arc<[ParseOut<'a>]>,
+    },
+    Optional(Option<Arc<ParseGrouping<'a>>>),
+    Out(Arc<ParseOut<'a>>),
+    Error {
+        skipped: &'a str,
+        error: Arc<ParseError<String>>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseOut<'a> {
-    pub rule: &'a str,
+    pub rule: SharedStr,
     pub out: ParseGrouping<'a>,
+    pub span: Span,
+    // Whitespace and comments skipped just before `span`, in order.
+    pub trivia: Vec<ParseOut<'a>>,
+    // Whether this is everything one call to `rule` matched, rather than a
+    // part of it. Recursive calls are labelled with the same rule as their
+    // caller, so this is where each call starts.
+    pub call: bool,
+    // Whether this is text matched by a literal terminal in the grammar, like
+    // `"+"`, rather than by a regex or a character class.
+    pub literal: bool,
+}
+
+impl<'a> ParseGrouping<'a> {
+    fn collect_errors<'b>(&'b self, errors: &mut Vec<&'b ParseError<String>>) {
+        match self {
+            ParseGrouping::Terminal(_) | ParseGrouping::Optional(None) => (),
+            ParseGrouping::Sequence { ts } => ts.iter().for_each(|t| t.out.collect_errors(errors)),
+            ParseGrouping::Optional(Some(out)) => out.collect_errors(errors),
+            ParseGrouping::Out(out) => out.out.collect_errors(errors),
+            ParseGrouping::Error { error, .. } => errors.push(error),
+        }
+    }
+}
+
+impl<'a> ParseOut<'a> {
+    // The errors recovered from while parsing, in the order they appear.
+    pub fn errors(&self) -> Vec<&ParseError<String>> {
+        let mut errors = vec![];
+        self.out.collect_errors(&mut errors);
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+    use crate::LineIndex;
+
+    // Parses `source` with memoization off and then on, which should agree.
+    fn parse_span(grammar: &str, source: &str) -> Span {
+        let mut parser = test_parser(grammar);
+        let span = parser.parse(source).expect("Test input should parse").span;
+        parser.memoize = true;
+        assert_eq!(parser.parse(source).unwrap().span, span);
+        span
+    }
+
+    fn parts<'o, 'a>(out: &'o ParseOut<'a>) -> &'o [ParseOut<'a>] {
+        match &out.out {
+            ParseGrouping::Out(out) => parts(out),
+            ParseGrouping::Sequence { ts } => ts,
+            _ => std::slice::from_ref(out),
+        }
+    }
+
+    #[test]
+    fn direct_left_recursion_is_left_associative() {
+        let parser = test_parser(r#"expr = expr "+" term | term ; term = re"[0-9]+" ;"#);
+        let out = parser.parse("1+2+3").unwrap();
+
+        assert_eq!(out.span, Span::new(0, 5));
+        assert_eq!(parts(&out)[0].span, Span::new(0, 3));
+    }
+
+    #[test]
+    fn indirect_left_recursion() {
+        let grammar = r#"a = b "-" "x" | "x" ; b = a ;"#;
+        assert_eq!(parse_span(grammar, "x-x-x"), Span::new(0, 5));
+    }
+
+    #[test]
+    fn left_recursive_start_rule_matches_like_any_other_call() {
+        let expr = r#"expr = expr "+" term | term ; term = re"[0-9]+" ;"#;
+        let called = format!("s = expr ; {expr}");
+
+        assert_eq!(parse_span(expr, "1 + 2+3"), Span::new(0, 7));
+        assert_eq!(parse_span(&called, "1 + 2+3"), Span::new(0, 7));
+    }
+
+    // The first node under `out` that's part of `rule`.
+    fn find<'o, 'a>(out: &'o ParseOut<'a>, rule: &str) -> Option<&'o ParseOut<'a>> {
+        fn find_in<'o, 'a>(group: &'o ParseGrouping<'a>, rule: &str) -> Option<&'o ParseOut<'a>> {
+            match group {
+                ParseGrouping::Sequence { ts } => ts.iter().find_map(|t| find(t, rule)),
+                ParseGrouping::Optional(Some(group)) => find_in(group, rule),
+                ParseGrouping::Out(out) => find(out, rule),
+                _ => None,
+            }
+        }
+
+        if out.rule == rule {
+            return Some(out);
+        }
+        find_in(&out.out, rule)
+    }
+
+    #[test]
+    fn every_node_has_the_span_it_matched() {
+        let parser = test_parser(r#"s = a b? ; a = "x" | "yy" ; b = "z" ;"#);
+        let source = " yy\n z";
+        let out = parser.parse(source).unwrap();
+        let b = find(&out, "b").unwrap();
+
+        assert_eq!(out.span, Span::new(0, source.len()));
+        assert_eq!(find(&out, "a").unwrap().span.text(source), "yy");
+        assert_eq!(b.span.text(source), "z");
+
+        let (start, end) = LineIndex::new(source).span(b.span);
+        assert_eq!((start.line, start.col, end.line, end.col), (2, 2, 2, 3));
+    }
+
+    #[test]
+    fn empty_matches_have_empty_spans() {
+        let parser = test_parser(r#"s = "x" "y"? ;"#);
+
+        assert_eq!(parser.parse("x").unwrap().span, Span::new(0, 1));
+        assert_eq!(
+            test_parser(r#"s = "y"? ;"#).parse("").unwrap().span,
+            Span::new(0, 0)
+        );
+    }
+
+    #[test]
+    fn star_and_plus_match_like_their_bounds() {
+        for (short, bounds) in [("*", "{0,}"), ("+", "{1,}")] {
+            for source in ["", "a", "a a a"] {
+                let short = test_parser(&format!(r#"s = ("a"){short} EOF ;"#)).parse(source);
+                let bounds = test_parser(&format!(r#"s = ("a"){bounds} EOF ;"#)).parse(source);
+
+                assert_eq!(short.is_ok(), bounds.is_ok());
+                if let (Ok(short), Ok(bounds)) = (short, bounds) {
+                    assert_eq!(parts(&short).len(), parts(&bounds).len());
+                }
+            }
+        }
+    }
+
+    // Grammars can't repeat something that matches nothing, so these are
+    // parsed directly.
+    #[test]
+    fn repetitions_keep_an_iteration_that_consumes_nothing() {
+        let parser = test_parser(r#"s = "a" ;"#);
+        let maybe_a = || {
+            Box::new(ParseExpr::Optional {
+                e: Box::new(ParseExpr::Atomic(AtomicExpr::Terminal("a".into()))),
+            })
+        };
+
+        for e in [
+            ParseExpr::ZeroOrMore { e: maybe_a() },
+            ParseExpr::OneOrMore { e: maybe_a() },
+            ParseExpr::Repeat {
+                e: maybe_a(),
+                min: 0,
+                max: Some(usize::MAX),
+            },
+            ParseExpr::Repeat {
+                e: maybe_a(),
+                min: 3,
+                max: Some(usize::MAX),
+            },
+        ] {
+            let mut idx = 0;
+            let out = e
+                .parse(
+                    &mut vec!["s".into()],
+                    &false,
+                    &parser,
+                    "aab",
+                    &mut idx,
+                    &mut Memo::default(),
+                )
+                .into_result()
+                .unwrap();
+
+            assert_eq!(idx, 2);
+            assert_eq!(parts(&out).len(), 3);
+        }
+    }
+
+    #[test]
+    fn separated_lists_keep_only_their_items() {
+        let list = test_parser(r#"s = ("a" % ",") EOF ;"#);
+        let trailing = test_parser(r#"s = ("a" %% ",") EOF ;"#);
+
+        let out = list.parse("a, a ,a").unwrap();
+        assert_eq!(parts(&parts(&out)[0]).len(), 3);
+        assert!(list.parse("a,").is_err());
+        assert!(list.parse("").is_err());
+        assert!(trailing.parse("a, a,").is_ok());
+        assert!(trailing.parse(",").is_err());
+    }
+
+    #[test]
+    fn separated_lists_stop_when_nothing_is_consumed() {
+        let parser = test_parser(r#"s = "a" ;"#);
+        let maybe = |term: &str| {
+            Box::new(ParseExpr::Optional {
+                e: Box::new(ParseExpr::Atomic(AtomicExpr::Terminal(term.into()))),
+            })
+        };
+        let e = ParseExpr::Separated {
+            e: maybe("a"),
+            sep: maybe(","),
+            trailing: false,
+        };
+
+        let mut idx = 0;
+        let result = e.parse(
+            &mut vec!["s".into()],
+            &false,
+            &parser,
+            "a,ab",
+            &mut idx,
+            &mut Memo::default(),
+        );
+        assert!(result.into_result().is_ok());
+        assert_eq!(idx, 3);
+    }
+
+    #[test]
+    fn lookahead_consumes_nothing() {
+        let grammar = r#"s = &"a" word ; word = re"[a-z]+" ;"#;
+
+        assert_eq!(parse_span(grammar, "abc"), Span::new(0, 3));
+        assert!(test_parser(grammar).parse("bc").is_err());
+    }
+
+    #[test]
+    fn failed_negative_lookahead_quotes_what_it_matched() {
+        let parser = test_parser(r#"s = "if" !ALNUM re"[a-z]+" ; ALNUM = re"[a-z0-9]" ;"#);
+
+        for source in ["ifx", "if x", "if\n  x"] {
+            match parser.parse(source) {
+                Err(ParseError::BadMatchError(e)) => {
+                    assert_eq!(e.msg, "Unexpected `x` here.", "{source:?}")
+                }
+                other => panic!("Expected a bad match, got {other:?}"),
+            }
+        }
+
+        let parser = test_parser(
+            r##"s = "if" !ALNUM re"[a-z]+" ; ALNUM = re"[a-z0-9]" ; COMMENT = _"#" ;"##,
+        );
+        match parser.parse("if#x") {
+            Err(ParseError::BadMatchError(e)) => assert_eq!(e.msg, "Unexpected `x` here."),
+            other => panic!("Expected a bad match, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn eof_matches_only_at_the_end() {
+        let parser = test_parser(r#"s = "a" EOF ;"#);
+
+        let out = parser.parse("a \n").unwrap();
+        // EOF is matched after the whitespace in front of it.
+        let eof = parts(parts(&out).last().unwrap()).last().unwrap();
+        assert!(matches!(eof.out, ParseGrouping::Terminal("")));
+        assert_eq!(eof.span, Span::new(3, 3));
+        assert!(parser.parse("a").is_ok());
+        for (source, offset) in [("ab", 1), ("a b", 2)] {
+            let e = parser.parse(source).unwrap_err();
+            assert_eq!(e.message(), Some("Expected end of input here."));
+            assert_eq!(e.offset(), Some(offset));
+        }
+    }
+
+    #[test]
+    fn bounded_repetition_matches_between_min_and_max() {
+        let grammar = r#"s = "x" (_[0-9a-f]){4} "y" ;"#;
+
+        assert_eq!(parse_span(grammar, "x12ab y"), Span::new(0, 7));
+        let too_few = test_parser(grammar).parse("x12a y").unwrap_err();
+        assert_eq!(too_few.message(), Some("Expected `[0-9a-f]` here."));
+        assert_eq!(too_few.offset(), Some(4));
+        let too_many = test_parser(grammar).parse("x12abc y").unwrap_err();
+        assert_eq!(too_many.offset(), Some(5));
+
+        let open = r#"s = ("-"){2,} ;"#;
+        assert_eq!(parse_span(open, "- - - -"), Span::new(0, 7));
+        assert!(test_parser(open).parse("-").is_err());
+    }
+
+    #[test]
+    fn grouped_repetition_is_one_token() {
+        let parser = test_parser(r#"s = hex EOF ; @hex = (_[0-9a-f]){2,3} ;"#);
+        let out = parser.parse("abc").unwrap();
+        let hex = find(&out, "hex").unwrap();
+
+        assert!(matches!(hex.out, ParseGrouping::Terminal("abc")));
+    }
 }
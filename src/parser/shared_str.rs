@@ -0,0 +1,120 @@
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use super::{Location, Span};
+
+// A string slice that owns a share of the text it was cut from. Slices of the
+// same grammar share one allocation, and can be traced back to where they
+// appear in it.
+#[derive(Clone)]
+pub struct SharedStr {
+    text: Arc<str>,
+    span: Span,
+}
+
+impl SharedStr {
+    pub fn new(text: &Arc<str>, span: Span) -> Self {
+        SharedStr {
+            text: Arc::clone(text),
+            span,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.span.text(&self.text)
+    }
+
+    // Where this slice is in the text it was cut from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    // Where this slice starts in `text`, if it was cut from it.
+    pub fn location_in(&self, text: &Arc<str>) -> Option<Location> {
+        if Arc::ptr_eq(&self.text, text) {
+            Some(Location::new(text, self.span.start))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&str> for SharedStr {
+    fn from(s: &str) -> Self {
+        SharedStr {
+            text: Arc::from(s),
+            span: Span::new(0, s.len()),
+        }
+    }
+}
+
+impl std::ops::Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SharedStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SharedStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for SharedStr {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SharedStr {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl std::fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for SharedStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -0,0 +1,125 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.start..self.end]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Location {
+    pub fn new(source: &str, idx: usize) -> Self {
+        LineIndex::new(source).location(idx)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.col)
+    }
+}
+
+// Byte offsets of the start of every line, so that many offsets into the same
+// source can be turned into line and column numbers cheaply.
+pub struct LineIndex<'s> {
+    source: &'s str,
+    line_starts: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    pub fn new(source: &'s str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn location(&self, idx: usize) -> Location {
+        let line = self.line_starts.partition_point(|start| *start <= idx);
+        let line_start = self.line_starts[line - 1];
+        let col = self.source[line_start..idx].chars().count() + 1;
+
+        Location { line, col }
+    }
+
+    pub fn span(&self, span: Span) -> (Location, Location) {
+        (self.location(span.start), self.location(span.end))
+    }
+}
+
+pub const TAB_WIDTH: usize = 4;
+
+// Replaces tabs with spaces up to the next tab stop, so that markers printed
+// under a line of source end up under the right character.
+pub fn expand_tabs(line: &str) -> String {
+    let mut out = String::new();
+    for c in line.chars() {
+        if c == '\t' {
+            let n = TAB_WIDTH - out.chars().count() % TAB_WIDTH;
+            out.push_str(&" ".repeat(n));
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+// The width of the first `chars` characters of `line` once tabs are expanded.
+pub fn display_width(line: &str, chars: usize) -> usize {
+    let prefix: String = line.chars().take(chars).collect();
+    expand_tabs(&prefix).chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_and_columns_count_from_one_in_characters() {
+        let source = "aé\nbé\té!\n";
+        let index = LineIndex::new(source);
+
+        assert_eq!(index.location(0), Location { line: 1, col: 1 });
+        assert_eq!(index.location(3), Location { line: 1, col: 3 });
+        assert_eq!(index.location(4), Location { line: 2, col: 1 });
+        assert_eq!(
+            index.location(source.find('!').unwrap()),
+            Location { line: 2, col: 5 }
+        );
+        assert_eq!(index.location(source.len()), Location { line: 3, col: 1 });
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_stop() {
+        assert_eq!(expand_tabs("\tx"), "    x");
+        assert_eq!(expand_tabs("ab\tx\t"), "ab  x   ");
+        assert_eq!(display_width("é\tx", 2), 4);
+        assert_eq!(display_width("é\tx", 3), 5);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use super::{compile_regex, AtomicExpr, GrammarError, Location, ParseExpr, Parser, SharedStr};
+
+impl Parser {
+    pub fn validate(&self) -> Vec<GrammarError> {
+        let mut errors = vec![];
+
+        if !self.rules.contains_key(&self.start) {
+            errors.push(GrammarError::UndefinedRule {
+                rule: self.start.to_string(),
+                location: self.locate(&self.start),
+            });
+        }
+
+        let mut defined: Vec<&SharedStr> = vec![];
+        for id in &self.definitions {
+            match defined.iter().find(|first| **first == id) {
+                Some(first) => errors.push(GrammarError::DuplicateRule {
+                    rule: id.to_string(),
+                    location: self.locate(id),
+                    first: self.locate(first),
+                }),
+                None => defined.push(id),
+            }
+        }
+
+        // Every definition is checked, including ones replaced by a later
+        // definition of the same rule.
+        let bodies: Vec<(&SharedStr, &ParseExpr)> = defined
+            .iter()
+            .map(|id| (*id, &self.rules[*id].0))
+            .chain(self.replaced.iter().map(|(id, rule)| (id, rule)))
+            .collect();
+
+        for (_, rule) in &bodies {
+            rule.visit_atomics(&mut |atomic| match atomic {
+                AtomicExpr::NonTerminal(non_term) if !self.rules.contains_key(non_term) => errors
+                    .push(GrammarError::UndefinedRule {
+                        rule: non_term.to_string(),
+                        location: self.locate(non_term),
+                    }),
+                AtomicExpr::Regex(re_str) if !self.regexes.contains_key(re_str) => {
+                    if let Err(e) = compile_regex(re_str) {
+                        errors.push(GrammarError::InvalidRegex {
+                            pattern: re_str.to_string(),
+                            msg: e.to_string(),
+                            location: self.locate(re_str),
+                        })
+                    }
+                }
+                AtomicExpr::Class(class) if class.is_backwards() => {
+                    errors.push(GrammarError::BackwardsRange {
+                        class: class.to_string(),
+                        location: self.locate(&class.text),
+                    })
+                }
+                _ => (),
+            });
+        }
+
+        let nullable = self.nullable_rules();
+        for (id, rule) in &bodies {
+            rule.visit(&mut |e| match e {
+                ParseExpr::ZeroOrMore { e }
+                | ParseExpr::OneOrMore { e }
+                | ParseExpr::Repeat { e, .. }
+                    if e.is_nullable(&nullable, self) =>
+                {
+                    errors.push(GrammarError::NullableRepetition {
+                        rule: id.to_string(),
+                        location: self.locate_expr(e),
+                    })
+                }
+                ParseExpr::Separated { e, sep, .. }
+                    if e.is_nullable(&nullable, self) && sep.is_nullable(&nullable, self) =>
+                {
+                    errors.push(GrammarError::NullableRepetition {
+                        rule: id.to_string(),
+                        location: self.locate_expr(e),
+                    })
+                }
+                ParseExpr::Repeat {
+                    e,
+                    min,
+                    max: Some(max),
+                } if max < min => errors.push(GrammarError::InvalidRepeat {
+                    rule: id.to_string(),
+                    min: *min,
+                    max: *max,
+                    location: self.locate_expr(e),
+                }),
+                _ => (),
+            });
+        }
+
+        // Trivia rules are used wherever tokens are.
+        let mut reachable = HashSet::from([&self.start]);
+        let mut stack = vec![&self.start];
+        for atomic in &self.trivia {
+            if let AtomicExpr::NonTerminal(id) = atomic {
+                reachable.insert(id);
+                stack.push(id);
+            }
+        }
+        while let Some(id) = stack.pop() {
+            if let Some((rule, _)) = self.rules.get(id) {
+                rule.visit_atomics(&mut |atomic| {
+                    if let AtomicExpr::NonTerminal(non_term) = atomic {
+                        if reachable.insert(non_term) {
+                            stack.push(non_term);
+                        }
+                    }
+                });
+            }
+        }
+
+        for id in defined {
+            if !reachable.contains(id) {
+                errors.push(GrammarError::UnreachableRule {
+                    rule: id.to_string(),
+                    location: self.locate(id),
+                });
+            }
+        }
+
+        errors
+    }
+
+    // Reports a problem with `e` at the first piece of grammar text inside it.
+    // Implicit whitespace is skipped since it doesn't come from the grammar.
+    fn locate_expr(&self, e: &ParseExpr) -> Option<Location> {
+        let mut location = None;
+        e.visit_atomics(&mut |atomic| match atomic {
+            AtomicExpr::Terminal(s) | AtomicExpr::Regex(s) | AtomicExpr::NonTerminal(s)
+                if location.is_none() =>
+            {
+                location = self.locate(s)
+            }
+            AtomicExpr::Class(class) if location.is_none() => location = self.locate(&class.text),
+            _ => (),
+        });
+
+        location
+    }
+
+    // Rule names and patterns share their text with the grammar they were
+    // cut from, so their position can be recovered from it.
+    fn locate(&self, s: &SharedStr) -> Option<Location> {
+        s.location_in(self.grammar.as_ref()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_parser;
+    use crate::GrammarErrors;
+
+    fn errors(grammar: &str) -> GrammarErrors {
+        Parser::from_grammar(grammar).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn undefined_rules_are_errors() {
+        let errors = errors(r#"s = t "a" ;"#);
+
+        assert!(matches!(&errors[..], [GrammarError::UndefinedRule { rule, .. }] if rule == "t"));
+    }
+
+    #[test]
+    fn every_definition_of_a_duplicate_rule_is_checked() {
+        let errors = errors(r#"s = t ; s = "a" ;"#);
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], GrammarError::DuplicateRule { rule, .. } if rule == "s"));
+        assert!(matches!(&errors[1], GrammarError::UndefinedRule { rule, .. } if rule == "t"));
+    }
+
+    #[test]
+    fn unreachable_rules_are_warnings() {
+        let parser = test_parser(r#"s = "a" ; t = "b" ; COMMENT = _"%" ;"#);
+
+        assert_eq!(parser.warnings.len(), 1);
+        assert!(parser.warnings[0].is_warning());
+        assert!(
+            matches!(&parser.warnings[0], GrammarError::UnreachableRule { rule, .. } if rule == "t")
+        );
+    }
+
+    #[test]
+    fn repeating_what_can_match_nothing_is_an_error() {
+        for grammar in [
+            r#"s = ("x"?)* ;"#,
+            r#"s = (re"\s*")+ ;"#,
+            r#"s = (a)+ ; a = "x"? ;"#,
+            r#"s = (a){2,} ; a = "x"? ;"#,
+            r#"s = ("x"?){0,3} ;"#,
+            r#"s = ("x"?) % (","?) ;"#,
+        ] {
+            let errors = errors(grammar);
+            assert!(
+                matches!(&errors[..], [GrammarError::NullableRepetition { rule, .. }] if rule == "s"),
+                "{grammar}: {errors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn repeating_what_always_consumes_is_fine() {
+        for grammar in [r#"s = ("x" "y"?)* ;"#, r#"s = ("x"?) % "," ;"#] {
+            assert!(errors(grammar).is_empty(), "{grammar}");
+        }
+    }
+
+    #[test]
+    fn bounds_must_not_be_backwards() {
+        let errors = errors(r#"s = ("a"){3,2} ;"#);
+
+        assert!(matches!(
+            &errors[..],
+            [GrammarError::InvalidRepeat { min: 3, max: 2, .. }]
+        ));
+        assert!(self::errors(r#"s = ("a"){2,2} ;"#).is_empty());
+    }
+}